@@ -1,16 +1,124 @@
-use std::{path::Path, sync::mpsc, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    sync::mpsc,
+    time::Duration,
+};
 
-use crate::{data, engine::GenkitEngine, Generator};
-use anyhow::Result;
+use crate::{
+    data,
+    engine::{BuildReport, GenkitEngine},
+    helpers, jinja, Generator,
+};
+use anyhow::{bail, Result};
 use notify_debouncer_mini::{new_debouncer, notify::RecursiveMode, DebouncedEventKind};
+use parking_lot::RwLock;
+use serde::Serialize;
 use tokio::sync::broadcast::Sender;
 
+static BUILD_STATUS: RwLock<BuildStatus> = parking_lot::const_rwlock(BuildStatus {
+    state: BuildState::Building,
+    last_build_time: None,
+    error: None,
+});
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum BuildState {
+    Building,
+    Ready,
+    Error,
+}
+
+/// The status of the latest build, served by the `/__genkit/status` endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct BuildStatus {
+    state: BuildState,
+    // The finished time of the latest build in RFC3339.
+    last_build_time: Option<String>,
+    error: Option<String>,
+}
+
+pub(crate) fn get_build_status() -> BuildStatus {
+    BUILD_STATUS.read().clone()
+}
+
+// Mark the build ready without building, such as serving a prebuilt directory.
+pub(crate) fn mark_build_ready() {
+    BUILD_STATUS.write().state = BuildState::Ready;
+}
+
+// Build and track the build status.
+fn build_with_status<G: Generator + Send>(
+    engine: &mut GenkitEngine<G>,
+    reload: bool,
+) -> Result<BuildReport> {
+    BUILD_STATUS.write().state = BuildState::Building;
+    let result = engine.build(reload);
+
+    let mut status = BUILD_STATUS.write();
+    status.last_build_time = time::OffsetDateTime::now_utc()
+        .format(&time::format_description::well_known::Rfc3339)
+        .ok();
+    match result.as_ref() {
+        Ok(_) => {
+            status.state = BuildState::Ready;
+            status.error = None;
+        }
+        Err(err) => {
+            status.state = BuildState::Error;
+            status.error = Some(err.to_string());
+        }
+    }
+    result
+}
+
+/// The external command to run after a successful build.
+pub(crate) struct PostBuildCommand {
+    pub command: String,
+    // Whether run the command after every rebuild in watch mode.
+    pub on_watch: bool,
+}
+
+impl PostBuildCommand {
+    // Run the command by the shell with the `GENKIT_SOURCE` and `GENKIT_DEST`
+    // environment variables, fail if the command returns non-zero.
+    fn run(&self, source: &Path, dest: &Path) -> Result<()> {
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C");
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c");
+            command
+        };
+        println!("Running post build command: {}", self.command);
+        let output = command
+            .arg(&self.command)
+            .env("GENKIT_SOURCE", source)
+            .env("GENKIT_DEST", dest)
+            .output()?;
+        print!("{}", String::from_utf8_lossy(&output.stdout));
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        if !output.status.success() {
+            bail!(
+                "Post build command `{}` failed with {}",
+                self.command,
+                output.status
+            );
+        }
+        Ok(())
+    }
+}
+
 pub(crate) async fn watch_build<G, P: AsRef<Path>>(
     generator: G,
     source: P,
     dest: P,
     watch: bool,
     sender: Option<Sender<()>>,
+    post_build: Option<PostBuildCommand>,
 ) -> Result<()>
 where
     G: Generator + Send + 'static,
@@ -18,10 +126,33 @@ where
     let source = std::fs::canonicalize(source)?;
     let source_path = source.clone();
     data::load(&source);
-    let mut engine = GenkitEngine::new(&source, dest, generator)?;
+    let mut engine = GenkitEngine::new(&source, &dest, generator)?;
+    // The dest directory has been created by the engine.
+    let dest = std::fs::canonicalize(dest)?;
+    if let Ok(relative) = dest.strip_prefix(&source) {
+        if relative.as_os_str().is_empty() {
+            bail!(
+                "The dest directory `{}` is the source directory, please use a dest outside \
+                 of the source or a subdirectory such as `build`.",
+                dest.display()
+            );
+        }
+        // Exclude the output from the sources, the changes in the dest
+        // are also ignored by the watcher below. Otherwise every build
+        // writes into the watched directory and triggers another build infinitely.
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        helpers::add_exclude_glob(format!("{relative}/**"));
+    }
+    // Only run the post build command on watch rebuild if asked.
+    let post_build = post_build.filter(|post_build| !watch || post_build.on_watch);
     // Spawn the build process as a blocking task, avoid starving other tasks.
     let build_result = tokio::task::spawn_blocking(move || {
-        engine.build(false)?;
+        build_with_status(&mut engine, false)?;
+        if watch {
+            if let Some(Err(err)) = post_build.as_ref().map(|p| p.run(&source, &dest)) {
+                println!("post build error: {err}");
+            }
+        }
 
         if let Some(sender) = sender.as_ref() {
             // Notify the first building finished.
@@ -53,22 +184,39 @@ where
                 }
             }
 
+            // The data file is exported after each build, never rebuild on its change.
+            let data_file = data::data_file(&source);
             loop {
                 match rx.recv() {
                     Ok(result) => match result {
                         Ok(events) => {
-                            // Prevent build too frequently, otherwise it will cause program stuck.
-                            if events
+                            let changed_paths = events
                                 .iter()
-                                .any(|event| event.kind == DebouncedEventKind::Any)
-                            {
-                                match engine.build(true) {
-                                    Ok(_) => {
+                                .filter(|event| {
+                                    event.kind == DebouncedEventKind::Any
+                                        && !event.path.starts_with(&dest)
+                                        && event.path != data_file
+                                })
+                                .map(|event| event.path.clone())
+                                .collect::<Vec<_>>();
+                            // Prevent build too frequently, otherwise it will cause program stuck.
+                            if !changed_paths.is_empty() {
+                                match build_with_status(&mut engine, true) {
+                                    Ok(report) => {
+                                        println!(
+                                            "{}",
+                                            rebuild_summary(&source, &changed_paths, &report)
+                                        );
                                         if let Some(sender) = sender.as_ref() {
                                             sender.send(())?;
                                         }
                                         // Export data to file after build
                                         data::export(&source).unwrap();
+                                        if let Some(Err(err)) =
+                                            post_build.as_ref().map(|p| p.run(&source, &dest))
+                                        {
+                                            println!("post build error: {err}");
+                                        }
                                     }
                                     Err(err) => {
                                         println!("build error: {:?}", &err);
@@ -85,6 +233,9 @@ where
             }
         } else {
             data::export(&source).unwrap();
+            if let Some(post_build) = post_build.as_ref() {
+                post_build.run(&source, &dest)?;
+            }
         }
         anyhow::Ok(())
     })
@@ -99,3 +250,89 @@ where
     }
     Ok(())
 }
+
+// The one-line summary of the rebuild in watch mode, such as:
+// `[12:30:00] Rebuilt in 120ms, 3 pages written, changed: posts/a.md`
+fn rebuild_summary(source: &Path, changed_paths: &[PathBuf], report: &BuildReport) -> String {
+    // Show at most 3 changed paths to keep the summary in one line.
+    const MAX_PATHS: usize = 3;
+
+    let mut paths = changed_paths
+        .iter()
+        .map(|path| {
+            path.strip_prefix(source)
+                .unwrap_or(path)
+                .display()
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths.dedup();
+    let mut changed = paths
+        .iter()
+        .take(MAX_PATHS)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if paths.len() > MAX_PATHS {
+        changed.push_str(&format!(" and {} more", paths.len() - MAX_PATHS));
+    }
+
+    // The time with the configured offset, see `Genkit::utc_offset`.
+    let now = jinja::now_local();
+    let format =
+        time::format_description::parse("[hour]:[minute]:[second]").expect("Invalid time format");
+    format!(
+        "[{}] Rebuilt in {}ms, {} pages written, changed: {changed}",
+        now.format(&format).unwrap_or_default(),
+        report.duration.as_millis(),
+        report.pages_written,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    use super::{rebuild_summary, BuildState, BuildStatus};
+    use crate::engine::BuildReport;
+
+    #[test]
+    fn test_build_status_json() {
+        let status = BuildStatus {
+            state: BuildState::Error,
+            last_build_time: Some("2024-01-01T00:00:00Z".into()),
+            error: Some("Template not found".into()),
+        };
+        assert_eq!(
+            serde_json::to_string(&status).unwrap(),
+            r#"{"state":"error","last_build_time":"2024-01-01T00:00:00Z","error":"Template not found"}"#
+        );
+    }
+
+    #[test]
+    fn test_rebuild_summary() {
+        let source = Path::new("/site");
+        let report = BuildReport {
+            pages_written: 2,
+            duration: Duration::from_millis(120),
+        };
+        let paths = ["/site/a.md", "/site/b.md", "/site/a.md"].map(PathBuf::from);
+        let summary = rebuild_summary(source, &paths, &report);
+        assert!(summary.ends_with("Rebuilt in 120ms, 2 pages written, changed: a.md, b.md"));
+
+        let paths = [
+            "/site/a.md",
+            "/site/b.md",
+            "/site/c.md",
+            "/site/d.md",
+            "/site/e.md",
+        ]
+        .map(PathBuf::from);
+        let summary = rebuild_summary(source, &paths, &report);
+        assert!(summary.ends_with("changed: a.md, b.md, c.md and 2 more"));
+    }
+}
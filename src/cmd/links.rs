@@ -0,0 +1,114 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use clap::{Arg, Command};
+use serde::Serialize;
+
+use crate::{
+    data::{self, UrlPreviewInfo},
+    Cmd,
+};
+
+// The source group of the URLs whose sources are unknown.
+const UNKNOWN_SOURCE: &str = "(unknown)";
+
+pub(crate) struct LinksCmd;
+
+#[derive(Debug, Serialize)]
+struct Link {
+    url: String,
+    line: Option<usize>,
+    preview: Option<UrlPreviewInfo>,
+}
+
+#[async_trait::async_trait]
+impl Cmd for LinksCmd {
+    fn on_init(&self) -> clap::Command {
+        Command::new("links")
+            .args([
+                Arg::new("source")
+                    .help("The source directory")
+                    .required(false),
+                Arg::new("json")
+                    .long("json")
+                    .help("Print the report in JSON format")
+                    .action(clap::ArgAction::SetTrue)
+                    .required(false),
+            ])
+            .about("Report all outbound links grouped by source file")
+    }
+
+    async fn on_execute(&self, arg_matches: &clap::ArgMatches) -> anyhow::Result<()> {
+        let source = arg_matches
+            .get_one::<String>("source")
+            .cloned()
+            .unwrap_or_else(|| ".".into());
+
+        data::load(source);
+        let report = collect_links();
+        if arg_matches.get_flag("json") {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            for (file, links) in &report {
+                println!("\n{file}:");
+                for link in links {
+                    let status = if link.preview.is_some() {
+                        "previewed"
+                    } else {
+                        "no preview"
+                    };
+                    match link.line {
+                        Some(line) => println!("- {} (line {line}, {status})", link.url),
+                        None => println!("- {} ({status})", link.url),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Collect all outbound links from the url sources and previews,
+// grouped by the source file.
+fn collect_links() -> BTreeMap<String, Vec<Link>> {
+    let guard = data::read();
+    let url_sources = guard.get_all_url_sources();
+
+    let mut urls = url_sources
+        .iter()
+        .map(|kv| kv.key().to_owned())
+        .collect::<BTreeSet<_>>();
+    urls.extend(
+        guard
+            .get_all_previews()
+            .iter()
+            .map(|kv| kv.key().to_owned()),
+    );
+
+    let mut report = BTreeMap::<String, Vec<Link>>::new();
+    for url in urls {
+        let preview = guard.get_preview(&url);
+        match url_sources.get(&url).filter(|sources| !sources.is_empty()) {
+            Some(sources) => {
+                for source in sources.iter() {
+                    report.entry(source.file.clone()).or_default().push(Link {
+                        url: url.clone(),
+                        line: source.line,
+                        preview: preview.clone(),
+                    });
+                }
+            }
+            None => report
+                .entry(UNKNOWN_SOURCE.to_owned())
+                .or_default()
+                .push(Link {
+                    url,
+                    line: None,
+                    preview,
+                }),
+        }
+    }
+    report
+        .values_mut()
+        .for_each(|links| links.sort_by_key(|link| link.line));
+    report
+}
@@ -1,14 +1,25 @@
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::Result;
 use bytes::Bytes;
 use clap::{Arg, Command};
+use dashmap::DashMap;
 use futures::future::try_join_all;
 use http_body_util::Empty;
 use hyper::Request;
 use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+use percent_encoding::percent_decode_str;
 
-use crate::{data, Cmd};
+use crate::{
+    data::{self, UrlSource},
+    helpers, html, Cmd,
+};
 
 pub(crate) struct LintCmd;
 
@@ -25,6 +36,37 @@ impl Cmd for LintCmd {
                     .help("Enable CI mode. If lint failed will reture a non-zero code.")
                     .action(clap::ArgAction::SetTrue)
                     .required(false),
+                Arg::new("fail-on")
+                    .long("fail-on")
+                    .help("The conditions failing the lint in CI mode, the others are reported as warnings")
+                    .value_parser(["notfound", "redirected", "servererror", "timeout"])
+                    .value_delimiter(',')
+                    .default_value("notfound,servererror")
+                    .required(false),
+                Arg::new("since")
+                    .long("since")
+                    .help("Only lint the URLs from the files changed since the git ref")
+                    .required(false),
+                Arg::new("list-only")
+                    .long("list-only")
+                    .help("Only print the URLs would be checked without making requests")
+                    .action(clap::ArgAction::SetTrue)
+                    .required(false),
+                Arg::new("json")
+                    .long("json")
+                    .help("Print the URL list of `--list-only` in JSON format")
+                    .action(clap::ArgAction::SetTrue)
+                    .required(false),
+                Arg::new("dest")
+                    .long("dest")
+                    .help("Check the internal links of the HTML files in the build directory")
+                    .required(false),
+                Arg::new("url-scheme")
+                    .long("url-scheme")
+                    .help("The URL scheme of the build directory to resolve internal links")
+                    .value_parser(["directory", "html"])
+                    .default_value("directory")
+                    .required(false),
             ])
             .about("Lint the project")
     }
@@ -35,30 +77,116 @@ impl Cmd for LintCmd {
             .cloned()
             .unwrap_or_else(|| ".".into());
 
-        let success = lint_project(source).await?;
-        if !success && arg_matches.get_flag("ci") {
+        let since = arg_matches.get_one::<String>("since");
+        let (urls, url_sources) = collect_urls(source, since.map(String::as_str))?;
+        if arg_matches.get_flag("list-only") {
+            return list_urls(&urls, &url_sources, arg_matches.get_flag("json"));
+        }
+
+        let mut conditions = lint_project(urls, &url_sources).await?;
+        if let Some(dest) = arg_matches.get_one::<String>("dest") {
+            let scheme = match arg_matches
+                .get_one::<String>("url-scheme")
+                .map(String::as_str)
+            {
+                Some("html") => UrlScheme::Html,
+                _ => UrlScheme::Directory,
+            };
+            if !lint_internal_links(Path::new(dest), scheme)? {
+                // The unresolved internal links are treated as 404.
+                conditions.insert(UrlCondition::NotFound);
+            }
+        }
+
+        let fail_on = arg_matches
+            .get_many::<String>("fail-on")
+            .unwrap_or_default()
+            .filter_map(|name| UrlCondition::from_name(name))
+            .collect::<HashSet<_>>();
+        if arg_matches.get_flag("ci") && !conditions.is_disjoint(&fail_on) {
             std::process::exit(1);
         }
         Ok(())
     }
 }
 
-// Lint the project.
-// Return true if lint success.
-async fn lint_project<P: AsRef<Path>>(source: P) -> Result<bool> {
-    let tasks = {
-        data::load(source);
-        let guard = data::read();
-        let url_previews = guard.get_all_previews();
-        url_previews
+type UrlSources = Arc<DashMap<String, BTreeSet<UrlSource>>>;
+
+// Collect the URLs to lint, sorted and deduplicated.
+// If `since` is set, only collect the URLs from the files changed since the git ref.
+fn collect_urls<P: AsRef<Path>>(
+    source: P,
+    since: Option<&str>,
+) -> Result<(Vec<String>, UrlSources)> {
+    let changed_files = match since {
+        Some(git_ref) => Some(get_changed_files(source.as_ref(), git_ref)?),
+        None => None,
+    };
+
+    data::load(source);
+    let guard = data::read();
+    let url_previews = guard.get_all_previews();
+    let url_sources = guard.get_all_url_sources();
+    if changed_files.is_some() && url_sources.is_empty() {
+        println!("No URL source recorded, fallback to lint all URLs.");
+    }
+    let mut urls = url_previews
+        .iter()
+        .filter(|kv| {
+            let Some(changed_files) = changed_files.as_ref() else {
+                return true;
+            };
+            // Always lint the URL whose sources are unknown.
+            match url_sources.get(kv.key()) {
+                Some(sources) => sources.iter().any(|s| changed_files.contains(&s.file)),
+                None => true,
+            }
+        })
+        .map(|kv| kv.key().to_owned())
+        .collect::<Vec<_>>();
+    urls.sort();
+    urls.dedup();
+    Ok((urls, url_sources))
+}
+
+// Print the URLs would be checked without making requests.
+fn list_urls(urls: &[String], url_sources: &UrlSources, json: bool) -> Result<()> {
+    if json {
+        let list = urls
             .iter()
-            .map(|kv| {
-                let (url, _) = kv.pair();
-                check_url(url.to_owned())
+            .map(|url| {
+                let sources = url_sources
+                    .get(url)
+                    .map(|sources| sources.iter().cloned().collect::<Vec<_>>())
+                    .unwrap_or_default();
+                serde_json::json!({ "url": url, "sources": sources })
             })
-            .collect::<Vec<_>>()
-    };
+            .collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&list)?);
+    } else {
+        urls.iter().for_each(|url| print_url(url, url_sources));
+        println!("\n{} URLs would be checked.", urls.len());
+    }
+    Ok(())
+}
 
+// Print the URL with the sources to point out where the URL comes from.
+fn print_url(url: &str, url_sources: &UrlSources) {
+    match url_sources.get(url) {
+        Some(sources) if !sources.is_empty() => {
+            let sources = sources.iter().map(ToString::to_string).collect::<Vec<_>>();
+            println!("- {url} ({})", sources.join(", "));
+        }
+        _ => println!("- {url}"),
+    }
+}
+
+// Lint the URLs, return the abnormal conditions found.
+async fn lint_project(
+    urls: Vec<String>,
+    url_sources: &UrlSources,
+) -> Result<HashSet<UrlCondition>> {
+    let tasks = urls.into_iter().map(check_url).collect::<Vec<_>>();
     let conditions =
         try_join_all(tasks)
             .await?
@@ -78,20 +206,37 @@ async fn lint_project<P: AsRef<Path>>(source: P) -> Result<bool> {
     let check_condition = |condition, statement: &str| {
         if let Some(urls) = conditions.get(&condition) {
             println!("\nThe following URLs {statement}:");
-            urls.iter().for_each(|url| println!("- {url}"));
+            urls.iter().for_each(|url| print_url(url, url_sources));
         }
     };
     check_condition(UrlCondition::NotFound, "are 404");
     check_condition(UrlCondition::Redirected, "have been redirected");
     check_condition(UrlCondition::ServerError, "have a server error");
+    check_condition(UrlCondition::Timeout, "are timed out");
+
+    Ok(conditions.into_keys().collect())
+}
 
-    Ok(conditions.is_empty())
+// Get the files changed since the git ref, relative to the source directory.
+fn get_changed_files(source: &Path, git_ref: &str) -> Result<HashSet<String>> {
+    let source = source.to_string_lossy();
+    let output = helpers::run_command(
+        "git",
+        &["-C", &source, "diff", "--name-only", "--relative", git_ref],
+    )?;
+    Ok(output.lines().map(ToOwned::to_owned).collect())
 }
 
+// The timeout of checking a URL.
+const CHECK_URL_TIMEOUT: Duration = Duration::from_secs(10);
+
 async fn check_url(url: String) -> Result<(String, UrlCondition)> {
     let client = Client::builder(TokioExecutor::new()).build_http();
     let req = Request::head(url.as_str()).body(Empty::<Bytes>::new())?;
-    let resp = client.request(req).await?;
+    let Ok(resp) = tokio::time::timeout(CHECK_URL_TIMEOUT, client.request(req)).await else {
+        return Ok((url, UrlCondition::Timeout));
+    };
+    let resp = resp?;
 
     let status = resp.status();
     let condition = if status.as_u16() == 404 {
@@ -106,10 +251,198 @@ async fn check_url(url: String) -> Result<(String, UrlCondition)> {
     Ok((url, condition))
 }
 
+/// How the page URL maps to the output file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UrlScheme {
+    /// `/about` and `/about/` map to `about/index.html`.
+    Directory,
+    /// `/about` and `/about/` map to `about.html`.
+    Html,
+}
+
+// Check the internal links of all HTML files in the dest directory.
+// Return true if all internal links resolved.
+fn lint_internal_links(dest: &Path, scheme: UrlScheme) -> Result<bool> {
+    let mut unresolved = Vec::new();
+    for entry in walkdir::WalkDir::new(dest) {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("html") {
+            continue;
+        }
+
+        let page = path.strip_prefix(dest)?;
+        for link in html::parse_html_links(File::open(path)?) {
+            let Some(candidates) = resolve_internal_link(page, &link, scheme) else {
+                continue;
+            };
+            if !candidates.iter().any(|file| dest.join(file).is_file()) {
+                unresolved.push((page.to_owned(), link, candidates));
+            }
+        }
+    }
+
+    if !unresolved.is_empty() {
+        println!("\nThe following internal links are unresolved:");
+        for (page, link, candidates) in &unresolved {
+            let candidates = candidates
+                .iter()
+                .map(|file| file.display().to_string())
+                .collect::<Vec<_>>();
+            println!(
+                "- {link} (in {}, tried: {})",
+                page.display(),
+                candidates.join(", ")
+            );
+        }
+    }
+    Ok(unresolved.is_empty())
+}
+
+// Resolve the internal `link` of the `page` to the candidate files
+// relative to the dest directory, in order of priority.
+//
+// Return `None` if the link is not an internal link.
+fn resolve_internal_link(page: &Path, link: &str, scheme: UrlScheme) -> Option<Vec<PathBuf>> {
+    let path = link.split(['#', '?']).next().unwrap_or_default();
+    if path.is_empty() || path.starts_with("//") || path.contains(':') {
+        // Fragment only, protocol-relative or absolute URL, such as `mailto:`.
+        return None;
+    }
+
+    let path = percent_decode_str(path).decode_utf8_lossy();
+    let path = match path.strip_prefix('/') {
+        Some(path) => PathBuf::from(path),
+        // Relative to the directory of the page.
+        None => page.parent().unwrap_or(Path::new("")).join(path),
+    };
+    // Normalize the `.` and `..` components.
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component.as_os_str().to_str() {
+            Some(".") => {}
+            Some("..") => {
+                normalized.pop();
+            }
+            _ => normalized.push(component),
+        }
+    }
+
+    if normalized.as_os_str().is_empty() {
+        return Some(vec![PathBuf::from("index.html")]);
+    }
+    let index = normalized.join("index.html");
+    let has_extension = normalized.extension().is_some();
+    if has_extension && !path.ends_with('/') {
+        // The dotted directory, such as `/v1.2`, if the file is missing.
+        return Some(vec![normalized, index]);
+    }
+    Some(match scheme {
+        UrlScheme::Directory => vec![index, normalized],
+        UrlScheme::Html => vec![normalized.with_extension("html"), index, normalized],
+    })
+}
+
 #[derive(Debug, Hash, PartialEq, Eq)]
 enum UrlCondition {
     Normal,
     NotFound,
     Redirected,
     ServerError,
+    Timeout,
+}
+
+impl UrlCondition {
+    // Parse the condition from the name of `--fail-on`.
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "notfound" => Some(Self::NotFound),
+            "redirected" => Some(Self::Redirected),
+            "servererror" => Some(Self::ServerError),
+            "timeout" => Some(Self::Timeout),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{resolve_internal_link, UrlCondition, UrlScheme};
+
+    #[test]
+    fn test_url_condition_from_name() {
+        assert_eq!(
+            UrlCondition::from_name("notfound"),
+            Some(UrlCondition::NotFound)
+        );
+        assert_eq!(
+            UrlCondition::from_name("timeout"),
+            Some(UrlCondition::Timeout)
+        );
+        assert_eq!(UrlCondition::from_name("normal"), None);
+    }
+
+    fn resolve(page: &str, link: &str, scheme: UrlScheme) -> Option<Vec<PathBuf>> {
+        resolve_internal_link(Path::new(page), link, scheme)
+    }
+
+    #[test]
+    fn test_resolve_internal_link() {
+        let page = "posts/hello/index.html";
+        for link in ["/about", "/about/", "/about/#team", "../../about?a=1"] {
+            assert_eq!(
+                resolve(page, link, UrlScheme::Directory).unwrap(),
+                [PathBuf::from("about/index.html"), PathBuf::from("about")]
+            );
+            assert_eq!(
+                resolve(page, link, UrlScheme::Html).unwrap(),
+                [
+                    PathBuf::from("about.html"),
+                    PathBuf::from("about/index.html"),
+                    PathBuf::from("about"),
+                ]
+            );
+        }
+
+        assert_eq!(
+            resolve(page, "/", UrlScheme::Directory).unwrap(),
+            [PathBuf::from("index.html")]
+        );
+        assert_eq!(
+            resolve(page, "cover.png", UrlScheme::Html).unwrap(),
+            [
+                PathBuf::from("posts/hello/cover.png"),
+                PathBuf::from("posts/hello/cover.png/index.html"),
+            ]
+        );
+
+        assert_eq!(
+            resolve(page, "/my%20post/", UrlScheme::Directory).unwrap(),
+            [
+                PathBuf::from("my post/index.html"),
+                PathBuf::from("my post")
+            ]
+        );
+        assert_eq!(
+            resolve(page, "%E4%BD%A0%E5%A5%BD.png", UrlScheme::Html).unwrap(),
+            [
+                PathBuf::from("posts/hello/你好.png"),
+                PathBuf::from("posts/hello/你好.png/index.html"),
+            ]
+        );
+        for link in ["/v1.2", "/docs/3.x#install"] {
+            let dir = link.trim_start_matches('/').split('#').next().unwrap();
+            assert_eq!(
+                resolve(page, link, UrlScheme::Html).unwrap(),
+                [PathBuf::from(dir), PathBuf::from(dir).join("index.html")]
+            );
+        }
+
+        assert!(resolve(page, "#intro", UrlScheme::Directory).is_none());
+        assert!(resolve(page, "https://example.com", UrlScheme::Directory).is_none());
+        assert!(resolve(page, "//example.com", UrlScheme::Directory).is_none());
+        assert!(resolve(page, "mailto:a@example.com", UrlScheme::Directory).is_none());
+    }
 }
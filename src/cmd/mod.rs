@@ -2,11 +2,16 @@ use anyhow::Result;
 use async_trait::async_trait;
 use clap::{Arg, ArgAction, Command};
 mod build;
+mod links;
 mod lint;
+mod netlify;
+mod render;
 mod serve;
 
 pub(crate) use build::*;
+pub(crate) use links::LinksCmd;
 pub(crate) use lint::LintCmd;
+pub(crate) use render::RenderCmd;
 pub(crate) use serve::*;
 
 #[async_trait]
@@ -19,6 +24,12 @@ pub trait Cmd {
 pub(crate) fn build_root_command(root_command: Command) -> Command {
     let name = root_command.get_name().to_owned();
     root_command
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .global(true)
+                .help("The config file path. Default config file is `genkit.toml`"),
+        )
         .subcommand(
             Command::new("build")
                 .args([
@@ -29,7 +40,15 @@ pub(crate) fn build_root_command(root_command: Command) -> Command {
                         .long("watch")
                         .action(ArgAction::SetTrue)
                         .help("Enable watching"),
+                    Arg::new("only")
+                        .long("only")
+                        .help("Only build the sources matching the glob, such as `posts/2024/**`"),
+                    Arg::new("time")
+                        .long("time")
+                        .action(ArgAction::SetTrue)
+                        .help("Print the time cost of each build phase"),
                 ])
+                .args(markdown_config_args())
                 .about("Build the site"),
         )
         .subcommand(
@@ -47,7 +66,34 @@ pub(crate) fn build_root_command(root_command: Command) -> Command {
                         .short('o')
                         .action(ArgAction::SetTrue)
                         .help("Auto open browser after server started"),
+                    Arg::new("no-build")
+                        .long("no-build")
+                        .value_name("DIR")
+                        .conflicts_with("dest")
+                        .help("Serve the prebuilt directory without building and live reloading"),
+                    Arg::new("dest")
+                        .long("dest")
+                        .value_name("DIR")
+                        .help("Build into the directory instead of a temporary directory"),
+                    Arg::new("precompressed")
+                        .long("precompressed")
+                        .action(ArgAction::SetTrue)
+                        .help("Serve the precompressed `.br` or `.gz` files if exist"),
                 ])
+                .args(markdown_config_args())
                 .about("Serve the site"),
         )
 }
+
+// The args to override the markdown config provided by generator.
+fn markdown_config_args() -> [Arg; 2] {
+    [
+        Arg::new("highlight-theme")
+            .long("highlight-theme")
+            .help("Override the syntax highlight theme"),
+        Arg::new("no-highlight")
+            .long("no-highlight")
+            .action(ArgAction::SetTrue)
+            .help("Disable the syntax highlighting"),
+    ]
+}
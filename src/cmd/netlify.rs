@@ -0,0 +1,208 @@
+use std::{fs, path::Path};
+
+use super::serve::HeaderRule;
+
+// The files of the static hosts (Netlify, Cloudflare Pages) in the dest directory.
+const HEADERS_FILE: &str = "_headers";
+const REDIRECTS_FILE: &str = "_redirects";
+
+/// The rules parsed from the `_headers` and `_redirects` files.
+#[derive(Debug, Default)]
+pub(crate) struct HostRules {
+    pub redirects: Vec<Redirect>,
+    pub headers: Vec<HeaderRule>,
+}
+
+impl HostRules {
+    // Load the rules from the files in the `dir`, the absent file has no rules.
+    pub fn load(dir: &Path) -> Self {
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap_or_default();
+        HostRules {
+            redirects: parse_redirects(&read(REDIRECTS_FILE)),
+            headers: parse_headers(&read(HEADERS_FILE)),
+        }
+    }
+}
+
+/// A redirect rule, such as `/blog/* /posts/:splat 301`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Redirect {
+    from: String,
+    to: String,
+    pub status: u16,
+    // Whether apply the rule even if a file exists at the path.
+    pub force: bool,
+}
+
+impl Redirect {
+    /// Match the request `path`, return the target with the `:placeholder`
+    /// and `:splat` replaced if matched.
+    pub fn matches(&self, path: &str) -> Option<String> {
+        let mut from_segments = self.from.trim_end_matches('/').split('/');
+        let mut path_segments = path.trim_end_matches('/').split('/');
+        let mut params = Vec::new();
+        let mut splat = None;
+        loop {
+            match (from_segments.next(), path_segments.next()) {
+                // The splat matches the rest segments, including none.
+                (Some("*"), segment) => {
+                    let rest = segment.into_iter().chain(path_segments);
+                    splat = Some(rest.collect::<Vec<_>>().join("/"));
+                    break;
+                }
+                (Some(from), Some(segment)) if from.starts_with(':') => {
+                    params.push((from, segment));
+                }
+                (Some(from), Some(segment)) if from == segment => {}
+                (None, None) => break,
+                _ => return None,
+            }
+        }
+
+        let mut to = self.to.clone();
+        if let Some(splat) = splat {
+            to = to.replace(":splat", &splat);
+        }
+        // Replace the longer placeholder first, avoid `:id` replacing the `:identity`.
+        params.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+        for (name, value) in params {
+            to = to.replace(name, value);
+        }
+        Some(to)
+    }
+}
+
+/// Parse the `_redirects` file, one rule each line: `from to [status][!]`.
+/// The status defaults to 301, `!` forces the rule even if a file exists.
+///
+/// The query params and conditions of the rules are unsupported.
+pub(crate) fn parse_redirects(content: &str) -> Vec<Redirect> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let mut tokens = line.split_whitespace();
+            let (Some(from), Some(to)) = (tokens.next(), tokens.next()) else {
+                println!(
+                    "Warning: invalid rule `{line}` at line {} of `{REDIRECTS_FILE}`",
+                    index + 1
+                );
+                return None;
+            };
+            // Such as `/store id=:id /blog/:id`, the target follows the query params.
+            if !to.starts_with('/') && !to.contains("://") {
+                println!(
+                    "Warning: unsupported query params of rule `{line}` at line {} of `{REDIRECTS_FILE}`",
+                    index + 1
+                );
+                return None;
+            }
+            let (status, force) = tokens
+                .find_map(|token| {
+                    let (code, force) = match token.strip_suffix('!') {
+                        Some(code) => (code, true),
+                        None => (token, false),
+                    };
+                    code.parse::<u16>().ok().map(|status| (status, force))
+                })
+                .unwrap_or((301, false));
+            Some(Redirect {
+                from: from.to_owned(),
+                to: to.to_owned(),
+                status,
+                force,
+            })
+        })
+        .collect()
+}
+
+/// Parse the `_headers` file, the path line is followed by the indented
+/// `Name: value` lines.
+///
+/// The `*` segment of the path matches any segments, such as `/assets/*`.
+pub(crate) fn parse_headers(content: &str) -> Vec<HeaderRule> {
+    let mut rules: Vec<HeaderRule> = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace) {
+            let glob = trimmed
+                .split('/')
+                .map(|segment| if segment == "*" { "**" } else { segment })
+                .collect::<Vec<_>>()
+                .join("/");
+            rules.push(HeaderRule {
+                glob,
+                headers: Vec::new(),
+            });
+        } else if let (Some(rule), Some((name, value))) =
+            (rules.last_mut(), trimmed.split_once(':'))
+        {
+            rule.headers
+                .push((name.trim().to_owned(), value.trim().to_owned()));
+        } else {
+            println!(
+                "Warning: invalid header `{trimmed}` at line {} of `{HEADERS_FILE}`",
+                index + 1
+            );
+        }
+    }
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_headers, parse_redirects};
+
+    #[test]
+    fn test_parse_redirects() {
+        let redirects = parse_redirects(
+            "# comment\n\
+             /home / \n\
+             /blog/* /posts/:splat 302\n\
+             /users/:id/posts/:slug /u/:id/:slug 200!\n\
+             /store id=:id /blog/:id\n\
+             /invalid\n",
+        );
+        assert_eq!(redirects.len(), 3);
+        assert_eq!((redirects[0].status, redirects[0].force), (301, false));
+        assert_eq!(redirects[0].matches("/home/").as_deref(), Some("/"));
+        assert_eq!(redirects[0].matches("/home/a"), None);
+
+        assert_eq!(redirects[1].status, 302);
+        assert_eq!(
+            redirects[1].matches("/blog/2024/hello").as_deref(),
+            Some("/posts/2024/hello")
+        );
+        assert_eq!(redirects[1].matches("/blog").as_deref(), Some("/posts/"));
+        assert_eq!(redirects[1].matches("/about"), None);
+
+        assert_eq!((redirects[2].status, redirects[2].force), (200, true));
+        assert_eq!(
+            redirects[2].matches("/users/1/posts/hello").as_deref(),
+            Some("/u/1/hello")
+        );
+    }
+
+    #[test]
+    fn test_parse_headers() {
+        let rules = parse_headers(
+            "/*\n  X-Frame-Options: DENY\n\n/assets/*\n  Cache-Control: public, max-age=3600\n  Link: </style.css>; rel=preload\n",
+        );
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].glob, "/**");
+        assert_eq!(
+            rules[0].headers,
+            vec![("X-Frame-Options".to_owned(), "DENY".to_owned())]
+        );
+        assert_eq!(rules[1].glob, "/assets/**");
+        assert_eq!(rules[1].headers[0].1, "public, max-age=3600");
+        assert_eq!(rules[1].headers[1].1, "</style.css>; rel=preload");
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+};
+
+use anyhow::Result;
+use clap::{Arg, ArgAction, Command};
+use minijinja::context;
+
+use crate::{
+    config::GenkitConfig, data, entity::MarkdownConfig, jinja::init_environment,
+    markdown::MarkdownRender, markdown_config_override, Cmd,
+};
+
+pub(crate) struct RenderCmd;
+
+#[async_trait::async_trait]
+impl Cmd for RenderCmd {
+    fn on_init(&self) -> Command {
+        Command::new("render")
+            .args([
+                Arg::new("input")
+                    .help("The markdown file to render, `-` to read from stdin")
+                    .default_value("-"),
+                Arg::new("template")
+                    .long("template")
+                    .help("The template file to wrap the HTML, which is the `content` variable"),
+                Arg::new("toc")
+                    .long("toc")
+                    .action(ArgAction::SetTrue)
+                    .help("Pass the table of content as the `toc` variable of the template"),
+            ])
+            .args(super::markdown_config_args())
+            .about("Render the markdown to HTML and write to stdout")
+    }
+
+    async fn on_execute(&self, arg_matches: &clap::ArgMatches) -> Result<()> {
+        let markdown = match arg_matches.get_one::<String>("input").map(String::as_str) {
+            Some("-") | None => {
+                let mut markdown = String::new();
+                io::stdin().read_to_string(&mut markdown)?;
+                markdown
+            }
+            Some(path) => fs::read_to_string(path)?,
+        };
+        let template = match arg_matches.get_one::<String>("template") {
+            Some(path) => Some(fs::read_to_string(path)?),
+            None => None,
+        };
+
+        // The same precedence as the build and serve commands:
+        // CLI args > config file > defaults.
+        let genkit_config =
+            GenkitConfig::load(arg_matches.get_one::<String>("config").map(String::as_str))?;
+        let mut config = MarkdownConfig::default();
+        markdown_config_override(arg_matches, &genkit_config)?.apply(&mut config);
+        let enable_toc = arg_matches.get_flag("toc");
+
+        // The url preview block needs the data, and it blocks on
+        // its own runtime, so render in a blocking thread.
+        data::load(".");
+        let html = tokio::task::spawn_blocking(move || {
+            render(&markdown, &config, template.as_deref(), enable_toc)
+        })
+        .await??;
+
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(html.as_bytes())?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+// Render the markdown to HTML, wrap it with the `template` if present.
+fn render(
+    markdown: &str,
+    config: &MarkdownConfig,
+    template: Option<&str>,
+    enable_toc: bool,
+) -> Result<String> {
+    let mut render = MarkdownRender::new(config);
+    if let Some(visitor) = data::get_markdown_visitor() {
+        render.set_markdown_visitor(visitor);
+    }
+    if enable_toc {
+        render.enable_toc();
+    }
+    let html = render.try_render_html(markdown)?;
+    match template {
+        Some(template) => {
+            let toc = render.get_toc();
+            let env = init_environment();
+            Ok(env.render_str(template, context! { content => html, toc })?)
+        }
+        None => Ok(html),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, RenderCmd};
+    use crate::{config::GenkitConfig, entity::MarkdownConfig, markdown_config_override, Cmd};
+
+    #[test]
+    fn test_markdown_config_precedence() {
+        let config = |args: &[&str], genkit_config: &GenkitConfig| {
+            let arg_matches = RenderCmd.on_init().get_matches_from(args);
+            let mut config = MarkdownConfig::default();
+            markdown_config_override(&arg_matches, genkit_config)
+                .unwrap()
+                .apply(&mut config);
+            config
+        };
+        let genkit_config = GenkitConfig {
+            highlight_code: Some(false),
+            highlight_theme: Some("nord".into()),
+            ..Default::default()
+        };
+
+        let default = MarkdownConfig::default();
+        let config_default = config(&["render"], &GenkitConfig::default());
+        assert_eq!(config_default.highlight_code, default.highlight_code);
+        assert_eq!(config_default.highlight_theme, default.highlight_theme);
+
+        let config_file = config(&["render"], &genkit_config);
+        assert!(!config_file.highlight_code);
+        assert_eq!(config_file.highlight_theme, "nord");
+
+        let config_args = config(&["render", "--highlight-theme", "dracula"], &genkit_config);
+        assert_eq!(config_args.highlight_theme, "dracula");
+
+        let arg_matches =
+            RenderCmd
+                .on_init()
+                .get_matches_from(["render", "--highlight-theme", "not-exists"]);
+        assert!(markdown_config_override(&arg_matches, &genkit_config).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let config = MarkdownConfig::default();
+        let html = render("Hello *world*", &config, None, false).unwrap();
+        assert_eq!(html, "<p>Hello <em>world</em></p>\n");
+
+        let template = "<main>{{ content | safe }}{{ toc | length }}</main>";
+        let html = render("# Title", &config, Some(template), true).unwrap();
+        assert!(html.starts_with("<main><h1"));
+        assert!(html.ends_with("1</main>"));
+    }
+}
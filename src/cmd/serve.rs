@@ -4,73 +4,192 @@ use std::{
     future::Future,
     io,
     net::SocketAddr,
-    path::PathBuf,
+    path::{Component, Path, PathBuf},
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
 };
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use bytes::Bytes;
 use fastwebsockets::Frame;
-use http_body_util::Full;
-use hyper::{body::Incoming, server::conn::http1, Method, Request, Response, StatusCode};
+use http_body_util::{combinators::UnsyncBoxBody, BodyExt, Empty, Full};
+use hyper::{
+    body::Incoming,
+    header::{HeaderMap, HeaderName, HeaderValue, CACHE_CONTROL, CONTENT_TYPE, LOCATION},
+    server::conn::http1,
+    Method, Request, Response, StatusCode,
+};
 use hyper_util::{rt::TokioIo, service::TowerToHyperService};
+use parking_lot::RwLock;
+use percent_encoding::percent_decode_str;
 use tokio::{
     net::TcpListener,
-    sync::broadcast::{self, Sender},
+    sync::broadcast::{self, error::RecvError, Sender},
 };
 use tower::Service;
-use tower_http::services::ServeDir;
-
-use super::build::watch_build;
-use crate::Generator;
-
-pub(crate) async fn run_serve<G>(
-    generator: G,
-    source: &str,
-    mut port: u16,
-    open_browser: bool,
-    name: &str,
-    bannel: Option<&str>,
-) -> Result<()>
+use tower_http::{services::ServeDir, set_header::SetResponseHeader};
+
+use super::{
+    build::{get_build_status, mark_build_ready, watch_build},
+    netlify::{HostRules, Redirect},
+};
+use crate::{embed, helpers, Generator};
+
+/// The default websocket path of the live reload, see `Genkit::live_reload_path`.
+pub(crate) const DEFAULT_LIVE_RELOAD_PATH: &str = "/live_reload";
+/// The path of the live reload client script.
+pub(crate) const LIVE_RELOAD_SCRIPT_PATH: &str = "/__genkit/live_reload.js";
+
+// Check the websocket path of the live reload, which must be an absolute path
+// other than the root, otherwise it never matches or shadows the site.
+fn check_live_reload_path(path: &str) -> Result<()> {
+    if !path.starts_with('/') || path == "/" {
+        bail!("Invalid live reload path `{path}`, it must start with `/` and not be `/`");
+    }
+    Ok(())
+}
+
+/// The response headers set on the paths matching the glob, see [`crate::helpers::glob_match`].
+#[derive(Debug, Clone)]
+pub(crate) struct HeaderRule {
+    pub glob: String,
+    pub headers: Vec<(String, String)>,
+}
+
+pub(crate) struct ServeOptions<'a> {
+    pub source: &'a str,
+    pub port: u16,
+    pub open_browser: bool,
+    pub precompressed: bool,
+    // Serve the prebuilt directory without building and live reloading.
+    pub prebuilt: Option<PathBuf>,
+    // Build into the directory instead of the temporary directory.
+    pub dest: Option<PathBuf>,
+    pub name: &'a str,
+    pub banner: Option<&'a str>,
+    pub header_rules: &'a [HeaderRule],
+    pub live_reload_path: &'static str,
+}
+
+pub(crate) async fn run_serve<G>(generator: G, options: ServeOptions<'_>) -> Result<()>
 where
     G: Generator + Send + 'static,
 {
-    loop {
-        let tmp_dir = env::temp_dir().join(format!("__{}_build", name));
-        if tmp_dir.exists() {
-            // Remove cached build directory to invalidate the old cache.
-            fs::remove_dir_all(&tmp_dir)?;
+    let header_rules = Arc::new(parse_header_rules(options.header_rules)?);
+    check_live_reload_path(options.live_reload_path)?;
+    if let Some(dest) = options.dest.as_ref() {
+        fs::create_dir_all(dest)?;
+        let source = fs::canonicalize(options.source)?;
+        if source.starts_with(fs::canonicalize(dest)?) {
+            // Otherwise the build writes into the source directory.
+            bail!(
+                "The dest directory `{}` must not be or contain the source directory",
+                dest.display()
+            );
         }
+    }
+    let mut port = options.port;
+    loop {
+        let serve_root = match (options.prebuilt.as_ref(), options.dest.as_ref()) {
+            (Some(prebuilt), _) => {
+                if !prebuilt.is_dir() {
+                    bail!("The prebuilt directory `{}` not exists", prebuilt.display());
+                }
+                prebuilt.clone()
+            }
+            (None, Some(dest)) => dest.clone(),
+            (None, None) => {
+                let tmp_dir = env::temp_dir().join(format!("__{}_build", options.name));
+                if tmp_dir.exists() {
+                    // Remove cached build directory to invalidate the old cache.
+                    fs::remove_dir_all(&tmp_dir)?;
+                }
+                tmp_dir
+            }
+        };
         let addr = SocketAddr::from(([127, 0, 0, 1], port));
         let serving_url = format!("http://{addr}");
 
         match TcpListener::bind(addr).await {
             Ok(listener) => {
-                if let Some(bannel) = bannel {
-                    println!("{}", bannel);
+                if let Some(banner) = options.banner {
+                    println!("{}", banner);
                 }
                 println!("listening on {}", serving_url);
 
                 let (tx, mut rx) = broadcast::channel(16);
-                let serve_dir =
-                    ServeDir::new(&tmp_dir).fallback(FallbackService { tx: tx.clone() });
-
-                if open_browser {
+                let mut serve_dir = ServeDir::new(&serve_root);
+                if options.precompressed {
+                    // Serve the precompressed `.br` or `.gz` file if the client accepts
+                    // the encoding, the `Content-Encoding` header is set accordingly.
+                    serve_dir = serve_dir.precompressed_br().precompressed_gzip();
+                }
+                // No watcher to signal reloads if serving the prebuilt directory.
+                let live_reload = options.prebuilt.is_none().then(|| tx.clone());
+                let serve_dir = serve_dir.fallback(FallbackService {
+                    tx: live_reload,
+                    live_reload_path: options.live_reload_path,
+                });
+                let serve_dir = TrailingSlashRedirect {
+                    root: serve_root.clone(),
+                    inner: serve_dir,
+                };
+                // Always revalidate the served files, otherwise the browser may
+                // show the stale cached page after rebuilding.
+                let serve_dir = SetResponseHeader::overriding(
+                    serve_dir,
+                    CACHE_CONTROL,
+                    HeaderValue::from_static("no-cache"),
+                );
+                // Apply the `_headers` and `_redirects` files in the serve root,
+                // reload them after every build.
+                let host_rules = Arc::new(RwLock::new(load_host_rules(&serve_root)));
+                if options.prebuilt.is_none() {
+                    let mut build_rx = tx.subscribe();
+                    let root = serve_root.clone();
+                    let host_rules = Arc::clone(&host_rules);
                     tokio::spawn(async move {
-                        if rx.recv().await.is_ok() {
-                            opener::open(serving_url).unwrap();
+                        while !matches!(build_rx.recv().await, Err(RecvError::Closed)) {
+                            *host_rules.write() = load_host_rules(&root);
                         }
                     });
                 }
+                let serve_dir = HostRulesService {
+                    root: serve_root.clone(),
+                    rules: host_rules,
+                    inner: serve_dir,
+                };
+                // Applied last, so the rules can override the headers above.
+                let serve_dir = SetHeaderRules {
+                    rules: Arc::clone(&header_rules),
+                    inner: serve_dir,
+                };
 
-                let s = PathBuf::from(source);
-                tokio::spawn(async move {
-                    if let Err(err) = watch_build(generator, s, tmp_dir, true, Some(tx)).await {
-                        // handle the error here, for example by logging it or returning it to the caller
-                        println!("Watch build error: {err}");
+                if options.prebuilt.is_some() {
+                    mark_build_ready();
+                    if options.open_browser {
+                        opener::open(serving_url)?;
                     }
-                });
+                } else {
+                    if options.open_browser {
+                        tokio::spawn(async move {
+                            if rx.recv().await.is_ok() {
+                                opener::open(serving_url).unwrap();
+                            }
+                        });
+                    }
+
+                    let s = PathBuf::from(options.source);
+                    tokio::spawn(async move {
+                        if let Err(err) =
+                            watch_build(generator, s, serve_root, true, Some(tx), None).await
+                        {
+                            // handle the error here, for example by logging it or returning it to the caller
+                            println!("Watch build error: {err}");
+                        }
+                    });
+                }
                 let (stream, _) = listener.accept().await?;
                 let io = TokioIo::new(stream);
 
@@ -99,10 +218,265 @@ where
     Ok(())
 }
 
-// A fallback service to handle websocket request and ServeDir's 404 request.
+type HeaderRules = Vec<(String, HeaderMap)>;
+
+fn parse_header_rules(rules: &[HeaderRule]) -> Result<HeaderRules> {
+    rules
+        .iter()
+        .map(|rule| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in &rule.headers {
+                let (Ok(name), Ok(value)) = (
+                    HeaderName::from_bytes(name.as_bytes()),
+                    HeaderValue::from_str(value),
+                ) else {
+                    bail!("Invalid header `{name}: {value}` for path `{}`", rule.glob);
+                };
+                headers.append(name, value);
+            }
+            Ok((rule.glob.trim_start_matches('/').to_owned(), headers))
+        })
+        .collect()
+}
+
+// The headers of all rules matching the request path, the later rule
+// overrides the same header of the earlier rules.
+fn matched_headers(rules: &HeaderRules, path: &str) -> HeaderMap {
+    let path = path.trim_start_matches('/');
+    let mut matched = HeaderMap::new();
+    for (glob, headers) in rules {
+        if helpers::glob_match(glob, path) {
+            for name in headers.keys() {
+                matched.remove(name);
+                for value in headers.get_all(name) {
+                    matched.append(name.clone(), value.clone());
+                }
+            }
+        }
+    }
+    matched
+}
+
+// Override the response headers with the matched headers.
+fn set_headers(response_headers: &mut HeaderMap, headers: HeaderMap) {
+    for name in headers.keys() {
+        response_headers.remove(name);
+    }
+    for (name, value) in headers.iter() {
+        response_headers.append(name, value.clone());
+    }
+}
+
+// The rules of the `_headers` and `_redirects` files of the host.
+#[derive(Default)]
+struct LoadedHostRules {
+    redirects: Vec<Redirect>,
+    headers: HeaderRules,
+}
+
+fn load_host_rules(root: &Path) -> LoadedHostRules {
+    let rules = HostRules::load(root);
+    let headers = parse_header_rules(&rules.headers).unwrap_or_else(|err| {
+        println!("Warning: {err}");
+        Vec::new()
+    });
+    LoadedHostRules {
+        redirects: rules.redirects,
+        headers,
+    }
+}
+
+// Whether a file would be served at the path, which shadows the unforced redirects.
+fn file_exists(root: &Path, path: &str) -> bool {
+    let file = root.join(path.trim_start_matches('/'));
+    file.is_file() || file.join("index.html").is_file()
+}
+
+// A service to apply the redirect and header rules of the host,
+// the 200 rule rewrites the path and the 404 rule serves the path as not found.
+#[derive(Clone)]
+struct HostRulesService<S> {
+    root: PathBuf,
+    rules: Arc<RwLock<LoadedHostRules>>,
+    inner: S,
+}
+
+impl<S> Service<Request<Incoming>> for HostRulesService<S>
+where
+    S: Service<Request<Incoming>, Response = Response<BoxBody>, Error = Infallible>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Incoming>) -> Self::Future {
+        let path = req.uri().path().to_owned();
+        let (headers, redirect) = {
+            let rules = self.rules.read();
+            let redirect = rules.redirects.iter().find_map(|redirect| {
+                if !redirect.force && file_exists(&self.root, &path) {
+                    return None;
+                }
+                redirect.matches(&path).map(|to| (redirect.status, to))
+            });
+            (matched_headers(&rules.headers, &path), redirect)
+        };
+
+        let mut status = None;
+        if let Some((code, mut to)) = redirect {
+            if let Some(query) = req.uri().query().filter(|_| !to.contains('?')) {
+                to.push('?');
+                to.push_str(query);
+            }
+            match code {
+                // Rewrite the local path, proxying to the external url is unsupported.
+                200 | 404 => {
+                    if let Ok(uri) = to.parse() {
+                        *req.uri_mut() = uri;
+                        if code == 404 {
+                            status = Some(StatusCode::NOT_FOUND);
+                        }
+                    }
+                }
+                code => {
+                    let mut resp = Response::builder()
+                        .status(StatusCode::from_u16(code).unwrap_or(StatusCode::MOVED_PERMANENTLY))
+                        .header(LOCATION, to)
+                        .body(Empty::new().map_err(|never| match never {}).boxed_unsync())
+                        .unwrap();
+                    set_headers(resp.headers_mut(), headers);
+                    // Never cache the redirect, the browser caches the 301 permanently,
+                    // then keeps obeying the stale rule after `_redirects` changed.
+                    resp.headers_mut()
+                        .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+                    return Box::pin(async move { Ok(resp) });
+                }
+            }
+        }
+
+        let fut = self.inner.call(req);
+        Box::pin(async move {
+            let mut resp = fut.await?;
+            if let Some(status) = status {
+                *resp.status_mut() = status;
+            }
+            set_headers(resp.headers_mut(), headers);
+            Ok(resp)
+        })
+    }
+}
+
+// A service to set the headers of the matching header rules on the responses.
+#[derive(Clone)]
+struct SetHeaderRules<S> {
+    rules: Arc<HeaderRules>,
+    inner: S,
+}
+
+impl<S, B> Service<Request<Incoming>> for SetHeaderRules<S>
+where
+    S: Service<Request<Incoming>, Response = Response<B>, Error = Infallible>,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = Response<B>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Incoming>) -> Self::Future {
+        let headers = matched_headers(&self.rules, req.uri().path());
+        let fut = self.inner.call(req);
+        Box::pin(async move {
+            let mut resp = fut.await?;
+            set_headers(resp.headers_mut(), headers);
+            Ok(resp)
+        })
+    }
+}
+
+// A service to permanently redirect the directory path without trailing slash
+// to the path with trailing slash, such as `/about` to `/about/`, which is the
+// behavior of most static hosts (GitHub Pages, Netlify).
+//
+// Only the directory which has an `index.html` is redirected, otherwise
+// the request is passed to the inner service to respond a genuine 404.
+#[derive(Clone)]
+struct TrailingSlashRedirect<S> {
+    root: PathBuf,
+    inner: S,
+}
+
+type BoxBody = UnsyncBoxBody<Bytes, io::Error>;
+
+impl<S, B> Service<Request<Incoming>> for TrailingSlashRedirect<S>
+where
+    S: Service<Request<Incoming>, Response = Response<B>, Error = Infallible>,
+    S::Future: Send + 'static,
+    B: http_body::Body<Data = Bytes, Error = io::Error> + Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Incoming>) -> Self::Future {
+        let path = req.uri().path();
+        if !path.ends_with('/') && index_file(&self.root, path).is_some_and(|file| file.is_file()) {
+            let mut location = format!("{path}/");
+            if let Some(query) = req.uri().query() {
+                location.push('?');
+                location.push_str(query);
+            }
+            let resp = Response::builder()
+                .status(StatusCode::MOVED_PERMANENTLY)
+                .header(LOCATION, location)
+                .body(Empty::new().map_err(|never| match never {}).boxed_unsync())
+                .unwrap();
+            return Box::pin(async move { Ok(resp) });
+        }
+
+        let fut = self.inner.call(req);
+        Box::pin(async move { Ok(fut.await?.map(BodyExt::boxed_unsync)) })
+    }
+}
+
+// The `index.html` of the directory `path` under the `root`, the `path` is
+// percent-decoded. Return `None` if the `path` has any `..` or root component
+// after decoding, which ServeDir rejects too.
+fn index_file(root: &Path, path: &str) -> Option<PathBuf> {
+    let path = percent_decode_str(path).decode_utf8().ok()?;
+    let mut file = root.to_path_buf();
+    for component in Path::new(path.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(name) => file.push(name),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    file.push("index.html");
+    Some(file)
+}
+
+// A fallback service to handle websocket request, the build status request
+// and ServeDir's 404 request.
 #[derive(Clone)]
 struct FallbackService {
-    tx: Sender<()>,
+    // The sender to signal reloads, `None` if live reload is disabled.
+    tx: Option<Sender<()>>,
+    // The websocket path of the live reload.
+    live_reload_path: &'static str,
 }
 
 impl Service<Request<Incoming>> for FallbackService {
@@ -115,11 +489,12 @@ impl Service<Request<Incoming>> for FallbackService {
     }
 
     fn call(&mut self, mut req: Request<Incoming>) -> Self::Future {
-        let mut reload_rx = self.tx.subscribe();
+        let reload_rx = self.tx.as_ref().map(Sender::subscribe);
+        let live_reload_path = self.live_reload_path;
         let fut = async move {
             let path = req.uri().path();
-            match (req.method(), path) {
-                (&Method::GET, "/live_reload") => {
+            match (req.method(), path, reload_rx) {
+                (&Method::GET, path, Some(mut reload_rx)) if path == live_reload_path => {
                     // Check if the request is a websocket upgrade request.
                     if fastwebsockets::upgrade::is_upgrade_request(&req) {
                         let (response, websocket) =
@@ -153,7 +528,35 @@ impl Service<Request<Incoming>> for FallbackService {
                         Ok(Response::new(Full::from("Not a websocket request!")))
                     }
                 }
-                _ => {
+                (&Method::GET, LIVE_RELOAD_SCRIPT_PATH, Some(_)) => {
+                    let script = include_str!("../../templates/live_reload.js")
+                        .replace("__LIVE_RELOAD_PATH__", live_reload_path);
+                    let resp = Response::builder()
+                        .header(CONTENT_TYPE, "text/javascript")
+                        .body(Full::from(script))
+                        .unwrap();
+                    Ok(resp)
+                }
+                (&Method::GET, "/__genkit/status", _) => {
+                    let status = serde_json::to_string(&get_build_status()).unwrap();
+                    let resp = Response::builder()
+                        .header(CONTENT_TYPE, "application/json")
+                        .body(Full::from(status))
+                        .unwrap();
+                    Ok(resp)
+                }
+                (method, path, _) => {
+                    // Serve the embedded asset if no file exists in dest.
+                    if let (&Method::GET, Some(content)) = (method, embed::get_asset(path)) {
+                        let mime =
+                            mime_guess::from_path(path).first_or(mime_guess::mime::TEXT_HTML);
+                        let resp = Response::builder()
+                            .header(CONTENT_TYPE, mime.as_ref())
+                            .body(Full::from(content))
+                            .unwrap();
+                        return Ok(resp);
+                    }
+
                     // Return 404 not found response.
                     let resp = Response::builder()
                         .status(StatusCode::NOT_FOUND)
@@ -166,3 +569,69 @@ impl Service<Request<Incoming>> for FallbackService {
         Box::pin(fut)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::{
+        check_live_reload_path, index_file, matched_headers, parse_header_rules, HeaderRule,
+    };
+
+    #[test]
+    fn test_index_file() {
+        let root = env::temp_dir().join(format!("genkit-index-file-{}", std::process::id()));
+        fs::create_dir_all(root.join("my dir")).unwrap();
+        fs::write(root.join("my dir/index.html"), "").unwrap();
+
+        let file = index_file(&root, "/my%20dir").unwrap();
+        assert_eq!(file, root.join("my dir/index.html"));
+        assert!(file.is_file());
+        assert!(index_file(&root, "/about").is_some_and(|file| !file.is_file()));
+        assert_eq!(index_file(&root, "/../etc"), None);
+        assert_eq!(index_file(&root, "/my%20dir/%2E%2E/%2E%2E"), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_check_live_reload_path() {
+        assert!(check_live_reload_path("/live_reload").is_ok());
+        assert!(check_live_reload_path("/__genkit/ws").is_ok());
+        assert!(check_live_reload_path("live_reload").is_err());
+        assert!(check_live_reload_path("/").is_err());
+        assert!(check_live_reload_path("").is_err());
+    }
+
+    #[test]
+    fn test_header_rules() {
+        let rule = |glob: &str, headers: &[(&str, &str)]| HeaderRule {
+            glob: glob.to_owned(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        };
+        let rules = parse_header_rules(&[
+            rule("/**", &[("X-Frame-Options", "DENY")]),
+            rule("/assets/**", &[("Cache-Control", "max-age=31536000")]),
+            rule("/assets/*.woff2", &[("Access-Control-Allow-Origin", "*")]),
+            rule("/assets/dev.js", &[("Cache-Control", "no-store")]),
+        ])
+        .unwrap();
+
+        let headers = matched_headers(&rules, "/index.html");
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers["x-frame-options"], "DENY");
+
+        let headers = matched_headers(&rules, "/assets/font.woff2");
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers["cache-control"], "max-age=31536000");
+        assert_eq!(headers["access-control-allow-origin"], "*");
+
+        // The later rule wins.
+        let headers = matched_headers(&rules, "/assets/dev.js");
+        assert_eq!(headers["cache-control"], "no-store");
+
+        assert!(parse_header_rules(&[rule("/**", &[("Bad Header", "value")])]).is_err());
+    }
+}
@@ -1,5 +1,7 @@
-use std::collections::HashMap;
 use std::fmt::Write;
+use std::{borrow::Cow, collections::HashMap};
+
+use pulldown_cmark_escape::escape_html;
 
 use crate::markdown;
 
@@ -9,26 +11,38 @@ static DEFAULT_BG_COLOR: &str = "#e1eaff";
 static DEFAULT_BORDER_COLOR: &str = "#82a7fc";
 
 /// The CalloutBlock to highlight some pragraphs.
+///
+/// Besides the colors, the options are:
+/// - `title`: the title shown above the content, or the summary if collapsible.
+/// - `collapsible`: render as the `<details>` element.
+/// - `open`: whether the collapsible callout is expanded by default.
 pub(crate) struct CalloutBlock<'a> {
     bg_color: &'a str,
     border_color: &'a str,
+    title: Option<&'a str>,
+    collapsible: bool,
+    open: bool,
     content: &'a str,
 }
 
 impl<'a> CalloutBlock<'a> {
-    pub fn new(options: HashMap<String, &'a str>, block: &'a str) -> Self {
-        let (bg_color, border_color) = Self::parse_colors(&options);
+    pub fn new(options: &'a HashMap<String, Cow<'_, str>>, block: &'a str) -> Self {
+        let (bg_color, border_color) = Self::parse_colors(options);
+        let flag = |key: &str| options.get(key).is_some_and(|value| value == "true");
         CalloutBlock {
             bg_color,
             border_color,
+            title: options.get("title").map(AsRef::as_ref),
+            collapsible: flag("collapsible"),
+            open: flag("open"),
             content: block,
         }
     }
 
-    fn parse_colors(options: &HashMap<String, &'a str>) -> (&'a str, &'a str) {
+    fn parse_colors(options: &'a HashMap<String, Cow<'_, str>>) -> (&'a str, &'a str) {
         let (bg_color, border_color) = (
-            options.get("bg_color").cloned(),
-            options.get("border_color").cloned(),
+            options.get("bg_color").map(AsRef::as_ref),
+            options.get("border_color").map(AsRef::as_ref),
         );
 
         let (theme_bg_color, theme_border_color) =
@@ -62,10 +76,33 @@ impl<'a> CodeBlock for CalloutBlock<'a> {
             "background-color: {}; border-color: {}",
             self.bg_color, self.border_color,
         );
-        writeln!(&mut html, r#"<div class="callout" style="{}">"#, style)?;
-        let block_html = markdown::render_html(self.content);
+        let mut title = String::new();
+        if let Some(text) = self.title {
+            escape_html(&mut title, text)?;
+        }
+        if self.collapsible {
+            let open = if self.open { " open" } else { "" };
+            writeln!(
+                &mut html,
+                r#"<details class="callout" style="{}"{}>"#,
+                style, open
+            )?;
+            if self.title.is_some() {
+                writeln!(&mut html, r#" <summary>{}</summary>"#, title)?;
+            }
+        } else {
+            writeln!(&mut html, r#"<div class="callout" style="{}">"#, style)?;
+            if self.title.is_some() {
+                writeln!(&mut html, r#" <div class="callout-title">{}</div>"#, title)?;
+            }
+        }
+        let block_html = markdown::try_render_html(self.content)?;
         writeln!(&mut html, r#" <div>{}</div>"#, block_html)?;
-        writeln!(&mut html, r#"</div>"#)?;
+        if self.collapsible {
+            writeln!(&mut html, r#"</details>"#)?;
+        } else {
+            writeln!(&mut html, r#"</div>"#)?;
+        }
         Ok(html)
     }
 }
@@ -75,17 +112,46 @@ mod tests {
     use crate::code_blocks::Fenced;
 
     use super::CalloutBlock;
+    use crate::CodeBlock;
 
     #[test]
     fn test_parse_colors() {
         let fenced = Fenced::parse("callout, theme: red").unwrap();
-        let callout = CalloutBlock::new(fenced.options, "dummy");
+        let callout = CalloutBlock::new(&fenced.options, "dummy");
         assert_eq!(callout.bg_color, "#fde2e2");
         assert_eq!(callout.border_color, "#f98e8b");
 
         let fenced = Fenced::parse("callout, theme: red, bg_color: #123456").unwrap();
-        let callout = CalloutBlock::new(fenced.options, "dummy");
+        let callout = CalloutBlock::new(&fenced.options, "dummy");
         assert_eq!(callout.bg_color, "#123456");
         assert_eq!(callout.border_color, "#f98e8b");
     }
+
+    #[test]
+    fn test_collapsible_callout() {
+        let fenced =
+            Fenced::parse(r#"callout, title: "Heads up <1>", collapsible, kind: note"#).unwrap();
+        let html = CalloutBlock::new(&fenced.options, "Hello")
+            .render()
+            .unwrap();
+        assert!(html.starts_with(
+            r#"<details class="callout" style="background-color: #e1eaff; border-color: #82a7fc">"#
+        ));
+        assert!(html.contains(" <summary>Heads up &lt;1&gt;</summary>\n"));
+        assert!(html.ends_with("</details>\n"));
+
+        let fenced = Fenced::parse("callout, collapsible, open").unwrap();
+        let html = CalloutBlock::new(&fenced.options, "Hello")
+            .render()
+            .unwrap();
+        assert!(html.contains(r#"#82a7fc" open>"#));
+        assert!(!html.contains("<summary>"));
+
+        let fenced = Fenced::parse("callout, title: Note").unwrap();
+        let html = CalloutBlock::new(&fenced.options, "Hello")
+            .render()
+            .unwrap();
+        assert!(html.contains(r#"<div class="callout-title">Note</div>"#));
+        assert!(html.ends_with("</div>\n"));
+    }
 }
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap};
 
 use anyhow::{bail, Result};
 
@@ -18,10 +18,32 @@ pub(crate) const URL_PREVIEW: &str = "urlpreview";
 
 const ALL_CODE_BLOCKS: &[&str] = &[CALLOUT, QUOTE, URL_PREVIEW];
 
+/// The parsed info string of the fenced code block, such as:
+///
+/// ````markdown
+/// ```callout, theme: red, title = "Note: read me", open
+/// ```
+/// ````
+///
+/// The grammar of the info string is:
+///
+/// - The items are separated by commas, a trailing comma is allowed.
+/// - The first item is the `name`, the rest are the `options`.
+/// - An option is `key: value` or `key = value`, split at the first `:` or `=`.
+///   Both the key and value are trimmed, and the dashes in the key are
+///   replaced with underscores (`border-color` is `border_color`).
+/// - A value starting with a double or single quote is quoted, the quotes
+///   are removed and the quoted value can contain commas, `:` and `=`.
+///   A quote elsewhere is a plain char, such as `title: don't stop`.
+/// - In a quoted value, a backslash escapes the next char, such as `\"`
+///   and `\\`. The unquoted value is taken literally.
+/// - A bare key without value is a boolean flag, its value is `"true"`.
+/// - An invalid option, such as a key containing whitespace, is ignored
+///   with a warning.
 #[derive(Debug, Default, PartialEq, Eq)]
 pub struct Fenced<'a> {
     pub name: &'a str,
-    pub options: HashMap<String, &'a str>,
+    pub options: HashMap<String, Cow<'a, str>>,
 }
 
 impl<'a> Fenced<'a> {
@@ -34,28 +56,23 @@ impl<'a> Fenced<'a> {
         ALL_CODE_BLOCKS.contains(&self.name)
     }
 
-    pub(crate) fn parse(input: &'a str) -> Result<Self> {
-        let input = input.trim_end_matches(',');
+    /// Parse the info string of the fenced code block,
+    /// see [`Fenced`] for the grammar.
+    pub fn parse(input: &'a str) -> Result<Self> {
+        let input = input.trim().trim_end_matches(',');
         if input.is_empty() {
             return Ok(Self::empty());
         }
 
-        let mut raw = input.split(',');
-        match raw.next() {
+        let mut raw = split_items(input).into_iter();
+        match raw.next().map(str::trim) {
             Some(name) if !name.is_empty() => {
                 let options = raw
-                    .filter_map(|pair| {
-                        let mut v = pair.split(':').take(2);
-                        match (v.next(), v.next()) {
-                            (Some(key), Some(value)) => {
-                                // Replace key's dash to underscore.
-                                Some((key.trim().replace('-', "_"), value.trim()))
-                            }
-                            _ => {
-                                println!("Warning: invalid fenced options: {}", pair);
-                                None
-                            }
-                        }
+                    .filter_map(|item| {
+                        parse_option(item).or_else(|| {
+                            println!("Warning: invalid fenced options: {}", item);
+                            None
+                        })
                     })
                     .collect::<HashMap<_, _>>();
                 Ok(Fenced { name, options })
@@ -67,6 +84,123 @@ impl<'a> Fenced<'a> {
     }
 }
 
+impl std::fmt::Display for Fenced<'_> {
+    /// Format as the info string which can be parsed back by [`Fenced::parse`],
+    /// the options are sorted by key.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        let mut options = self.options.iter().collect::<Vec<_>>();
+        options.sort();
+        for (key, value) in options {
+            match value.as_ref() {
+                "true" => write!(f, ", {key}")?,
+                value if needs_quote(value) => {
+                    // Prefer the quote which needs no escape.
+                    let quote = if value.contains('"') && !value.contains('\'') {
+                        '\''
+                    } else {
+                        '"'
+                    };
+                    write!(f, ", {key}: {quote}")?;
+                    for c in value.chars() {
+                        if c == quote || c == '\\' {
+                            write!(f, "\\")?;
+                        }
+                        write!(f, "{c}")?;
+                    }
+                    write!(f, "{quote}")?;
+                }
+                value => write!(f, ", {key}: {value}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Split the items by commas outside of the quoted values.
+fn split_items(input: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut quote = None;
+    let mut escaped = false;
+    // Whether the `:` or `=` of the current item has been seen.
+    let mut has_value = false;
+    // Whether only whitespaces follow the `:` or `=`, the quote
+    // opens the quoted value only at the start of the value.
+    let mut value_start = false;
+    let mut start = 0;
+    for (i, c) in input.char_indices() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if q == c => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') if value_start => {
+                quote = Some(c);
+                value_start = false;
+            }
+            (None, ',') => {
+                items.push(&input[start..i]);
+                start = i + 1;
+                has_value = false;
+                value_start = false;
+            }
+            (None, ':' | '=') if !has_value => {
+                has_value = true;
+                value_start = true;
+            }
+            (None, c) if c.is_whitespace() => {}
+            (None, _) => value_start = false,
+        }
+    }
+    items.push(&input[start..]);
+    items
+}
+
+// Parse the `key: value`, `key = value` or bare `key` option.
+fn parse_option(item: &str) -> Option<(String, Cow<'_, str>)> {
+    let (key, value) = match item.find([':', '=']) {
+        Some(index) => {
+            let value = item[index + 1..].trim();
+            (&item[..index], unquote(value))
+        }
+        None => (item, Cow::Borrowed("true")),
+    };
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return None;
+    }
+    // Replace key's dash to underscore.
+    Some((key.replace('-', "_"), value))
+}
+
+// Remove the quotes and unescape the quoted value.
+fn unquote(value: &str) -> Cow<'_, str> {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|value| value.strip_suffix(quote))
+        {
+            if !inner.contains('\\') {
+                return Cow::Borrowed(inner);
+            }
+            let mut unescaped = String::with_capacity(inner.len());
+            let mut chars = inner.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => unescaped.extend(chars.next()),
+                    c => unescaped.push(c),
+                }
+            }
+            return Cow::Owned(unescaped);
+        }
+    }
+    Cow::Borrowed(value)
+}
+
+fn needs_quote(value: &str) -> bool {
+    value.is_empty() || value != value.trim() || value.contains([',', ':', '=', '"', '\''])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -98,4 +232,45 @@ mod tests {
         let fenced = Fenced::parse("rust").unwrap();
         assert!(!fenced.is_builtin_code_block());
     }
+
+    #[test]
+    fn test_fenced_grammar() {
+        let fenced = Fenced::parse(
+            r#"callout, title = "Note: a, b", open, url: https://a.com, x: 'say "hi"'"#,
+        )
+        .unwrap();
+        assert_eq!(fenced.name, "callout");
+        assert_eq!(fenced.options["title"], "Note: a, b");
+        assert_eq!(fenced.options["open"], "true");
+        assert_eq!(fenced.options["url"], "https://a.com");
+        assert_eq!(fenced.options["x"], r#"say "hi""#);
+
+        // The quote in the middle of the value doesn't open a quoted value.
+        let fenced = Fenced::parse("callout, title: don't stop, theme: red").unwrap();
+        assert_eq!(fenced.options["title"], "don't stop");
+        assert_eq!(fenced.options["theme"], "red");
+
+        let fenced = Fenced::parse(r#"callout, title: "say \"hi\", it's \\o/""#).unwrap();
+        assert_eq!(fenced.options["title"], r#"say "hi", it's \o/"#);
+
+        let fenced = Fenced::parse(" rust , , bad key ").unwrap();
+        assert_eq!(fenced.name, "rust");
+        assert!(fenced.options.is_empty());
+    }
+
+    #[test]
+    fn test_fenced_round_trip() {
+        for input in [
+            "rust",
+            "callout, border_color: #abcdef, theme: red",
+            "callout, open, title: \"Note: a, b\"",
+            "quote, empty: \"\", x: 'say \"hi\"'",
+            r#"callout, title: "say \"hi\", it's \\o/""#,
+            r#"callout, title: "don't stop""#,
+        ] {
+            let fenced = Fenced::parse(input).unwrap();
+            assert_eq!(fenced.to_string(), input);
+            assert_eq!(Fenced::parse(&fenced.to_string()).unwrap(), fenced);
+        }
+    }
 }
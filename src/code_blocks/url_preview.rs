@@ -1,8 +1,11 @@
-use std::{collections::HashMap, fmt::Write};
+use std::{borrow::Cow, collections::HashMap, fmt::Write};
 
 use anyhow::Result;
 
-use crate::data::{self, PreviewEvent, UrlPreviewInfo};
+use crate::{
+    data::{self, UrlPreviewInfo},
+    markdown,
+};
 
 use super::CodeBlock;
 
@@ -15,7 +18,7 @@ pub(crate) struct UrlPreviewBlock<'a> {
 
 impl<'a> UrlPreviewBlock<'a> {
     pub(crate) fn new(
-        options: HashMap<String, &'a str>,
+        options: HashMap<String, Cow<'_, str>>,
         url: &'a str,
         info: UrlPreviewInfo,
     ) -> Self {
@@ -24,7 +27,7 @@ impl<'a> UrlPreviewBlock<'a> {
             info,
             show_image: options
                 .get("image")
-                .and_then(|v| str::parse::<bool>(v).ok())
+                .and_then(|v| v.parse::<bool>().ok())
                 .unwrap_or(true),
         }
     }
@@ -63,34 +66,23 @@ impl<'a> CodeBlock for UrlPreviewError<'a> {
 
 /// Render the preview url if success, otherwise return preview error
 /// to remind user we have error.
-#[tokio::main(flavor = "current_thread")]
-pub(crate) async fn render(url: &str, options: HashMap<String, &str>) -> Option<String> {
-    let (first_preview, mut rx) = {
-        // parking_lot RwLock guard isn't async-aware,
-        // we should keep this guard drop in this scope.
+///
+/// The `line` is where the url located in the current source file.
+pub(crate) fn render(
+    url: &str,
+    options: HashMap<String, Cow<'_, str>>,
+    line: Option<usize>,
+) -> Option<String> {
+    {
         let data = data::read();
-        if let Some(info) = data.get_preview(url) {
-            let html = UrlPreviewBlock::new(options, url, info).render().unwrap();
-            return Some(html);
-        }
-
-        data.preview_url(url)
-    };
-    rx.changed()
-        .await
-        .expect("URL preview watch channel receive failed.");
-    let event = rx.borrow();
-    match event.to_owned().expect("Url preview didn't initialized.") {
-        PreviewEvent::Finished(info) => {
-            let html = UrlPreviewBlock::new(options, url, info).render().unwrap();
-            if first_preview {
-                println!("URL previewed: {url}");
-            }
-            Some(html)
-        }
-        PreviewEvent::Failed(err) => {
-            // Return a preview error block.
-            Some(UrlPreviewError(url, &err).render().unwrap())
+        data.mark_rendered(url);
+        if let Some(source) = markdown::current_source_file() {
+            data.record_url_source(url, &source, line);
         }
     }
+    match data::preview_url_blocking(url) {
+        Ok(info) => Some(UrlPreviewBlock::new(options, url, info).render().unwrap()),
+        // Return a preview error block.
+        Err(err) => Some(UrlPreviewError(url, &err.to_string()).render().unwrap()),
+    }
 }
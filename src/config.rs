@@ -0,0 +1,67 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+// The config file discovered in the current directory if no `--config` specified.
+const DEFAULT_CONFIG_FILE: &str = "genkit.toml";
+
+/// The config file of genkit, such as `genkit.toml`.
+///
+/// All fields are the defaults of the command line args, the precedence is:
+/// CLI args > `--config` file > default `genkit.toml` > built-in defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "snake_case")]
+pub(crate) struct GenkitConfig {
+    pub source: Option<String>,
+    pub dest: Option<String>,
+    pub port: Option<u16>,
+    pub highlight_code: Option<bool>,
+    pub highlight_theme: Option<String>,
+    /// The command to run after a successful build, with the `GENKIT_SOURCE`
+    /// and `GENKIT_DEST` environment variables.
+    pub post_build_command: Option<String>,
+    /// Whether run the `post_build_command` after every rebuild in watch mode.
+    pub post_build_on_watch: bool,
+}
+
+impl GenkitConfig {
+    /// Load the config from the explicit `path`, which must exist.
+    /// Otherwise load the default `genkit.toml` if it exists.
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        match path {
+            Some(path) => Self::from_file(path),
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => {
+                Self::from_file(DEFAULT_CONFIG_FILE)
+            }
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn from_file(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file `{path}`"))?;
+        toml::from_str(&content).with_context(|| format!("Invalid config file `{path}`"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::GenkitConfig;
+
+    #[test]
+    fn test_load_config() {
+        let path = env::temp_dir().join("genkit-config-preview.toml");
+        std::fs::write(&path, "dest = \"preview\"\nport = 4000\n").unwrap();
+        let config = GenkitConfig::load(path.to_str()).unwrap();
+        assert_eq!(config.dest.as_deref(), Some("preview"));
+        assert_eq!(config.port, Some(4000));
+        assert_eq!(config.source, None);
+
+        // The explicit config file must exist.
+        assert!(GenkitConfig::load(Some("not-exists.toml")).is_err());
+        assert!(toml::from_str::<GenkitConfig>("port = \"abc\"").is_err());
+    }
+}
@@ -0,0 +1,74 @@
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+
+use anyhow::{Context as _, Result};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+
+use crate::markdown;
+
+/// The custom parser to convert the content of a file type to HTML,
+/// the arguments are the file content and the file path.
+pub type ContentParser = dyn Fn(&str, &Path) -> Result<String> + Send + Sync;
+
+// The custom parsers keyed by the lowercase file extension.
+static CONTENT_PARSERS: Lazy<RwLock<HashMap<String, Arc<ContentParser>>>> =
+    Lazy::new(Default::default);
+
+pub(crate) fn register_parser(extension: &str, parser: Arc<ContentParser>) {
+    let extension = extension.trim_start_matches('.').to_lowercase();
+    CONTENT_PARSERS.write().insert(extension, parser);
+}
+
+fn get_parser(path: &Path) -> Option<Arc<ContentParser>> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    CONTENT_PARSERS.read().get(&extension).cloned()
+}
+
+/// Whether the file is a content file: the markdown (`.md`, `.markdown`)
+/// or the file type with a registered parser, see `Genkit::content_parser`.
+pub fn is_content_file(path: &Path) -> bool {
+    let is_markdown = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "md" | "markdown"));
+    is_markdown || get_parser(path).is_some()
+}
+
+/// Render the content file to HTML by the parser registered for its extension,
+/// otherwise render it as markdown, see [`markdown::render_html_file`].
+///
+/// The `path` is the current source file during the parsing.
+pub fn render_file(path: &Path, root: &Path) -> Result<String> {
+    match get_parser(path) {
+        Some(parser) => {
+            let content = fs::read_to_string(path)?;
+            markdown::with_source_file(path, || parser(&content, path))
+                .with_context(|| format!("Failed to parse `{}`", path.display()))
+        }
+        None => markdown::render_html_file(path, root),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path, sync::Arc};
+
+    use super::{is_content_file, register_parser, render_file};
+
+    #[test]
+    fn test_render_file() {
+        register_parser(
+            ".Upper",
+            Arc::new(|content: &str, _: &Path| Ok(format!("<p>{}</p>", content.to_uppercase()))),
+        );
+        assert!(is_content_file(Path::new("a.upper")));
+        assert!(is_content_file(Path::new("a.md")));
+        assert!(!is_content_file(Path::new("a.txt")));
+
+        let root = env::temp_dir().join("genkit-content");
+        fs::create_dir_all(&root).unwrap();
+        let file = root.join("hello.upper");
+        fs::write(&file, "hello").unwrap();
+        assert_eq!(render_file(&file, &root).unwrap(), "<p>HELLO</p>");
+    }
+}
@@ -1,10 +1,25 @@
 use anyhow::anyhow;
+use parking_lot::RwLock;
 use serde::ser::Serialize;
 use serde_json::value::{to_value, Map, Value};
 use std::collections::BTreeMap;
 
 use anyhow::Result;
 
+// The site-global values injected into every context the engine creates.
+static GLOBAL_CONTEXT: RwLock<Context> = parking_lot::const_rwlock(Context {
+    data: BTreeMap::new(),
+});
+
+pub(crate) fn insert_global<T: Serialize + ?Sized>(key: String, val: &T) {
+    GLOBAL_CONTEXT.write().insert(key, val);
+}
+
+/// Get a copy of the global context, see [`crate::Genkit::global_context`].
+pub(crate) fn global_context() -> Context {
+    GLOBAL_CONTEXT.read().clone()
+}
+
 /// The struct that holds the context of a template rendering.
 ///
 /// Light wrapper around a `BTreeMap` for easier insertions of Serializable
@@ -81,6 +96,36 @@ impl Context {
         self.data.append(&mut source.data);
     }
 
+    /// Create a child context inheriting all values of `self`, for the nested
+    /// rendering such as rendering the items of a list page. The child owns
+    /// a copy of the values, so the changes of the child never affect `self`.
+    ///
+    /// It's the same as `clone`, which is how the engine passes the context to
+    /// every entity: each entity gets its own copy of the global context.
+    ///
+    /// ```rust
+    /// # use genkit::Context;
+    /// let mut list = Context::new();
+    /// list.insert("title", &"Posts");
+    /// for post in ["a", "b"] {
+    ///     let item = list.child().with("post", post);
+    ///     assert_eq!(item.get("title"), list.get("title"));
+    /// }
+    /// assert!(!list.contains_key("post"));
+    /// ```
+    pub fn child(&self) -> Self {
+        self.clone()
+    }
+
+    /// Insert the `val` and return the context, to chain the overrides
+    /// of a child context: `context.child().with("a", &1).with("b", &2)`.
+    ///
+    /// Panics if the serialization fails, see [`Context::insert`].
+    pub fn with<T: Serialize + ?Sized, S: Into<String>>(mut self, key: S, val: &T) -> Self {
+        self.insert(key, val);
+        self
+    }
+
     /// Converts the context to a `serde_json::Value` consuming the context.
     pub fn into_json(self) -> Value {
         let mut m = Map::new();
@@ -128,4 +173,44 @@ impl Context {
     pub fn contains_key(&self, index: &str) -> bool {
         self.data.contains_key(index)
     }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.data.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::{global_context, insert_global, Context};
+
+    #[test]
+    fn test_global_context() {
+        insert_global("site_name".into(), "Genkit");
+        insert_global("version".into(), &1);
+
+        // The entity's value wins on key conflict.
+        let mut context = global_context();
+        context.insert("version", &2);
+        assert_eq!(context.get("site_name"), Some(&Value::from("Genkit")));
+        assert_eq!(context.get("version"), Some(&Value::from(2)));
+        assert!(!Context::new().contains_key("site_name"));
+    }
+
+    #[test]
+    fn test_child_context() {
+        let mut parent = Context::new();
+        parent.insert("title", "Posts");
+        parent.insert("page", &1);
+
+        let child = parent.child().with("page", &2).with("post", "hello");
+        assert_eq!(child.get("title"), Some(&Value::from("Posts")));
+        assert_eq!(child.get("page"), Some(&Value::from(2)));
+        assert_eq!(child.get("post"), Some(&Value::from("hello")));
+
+        // The parent is untouched.
+        assert_eq!(parent.get("page"), Some(&Value::from(1)));
+        assert!(!parent.contains_key("post"));
+    }
 }
@@ -1,8 +1,8 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fs::{self, File},
     io::Write,
-    path::Path,
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
@@ -10,7 +10,7 @@ use std::{
 };
 
 use anyhow::Result;
-use dashmap::{try_result::TryResult, DashMap};
+use dashmap::{try_result::TryResult, DashMap, DashSet};
 use once_cell::sync::OnceCell;
 use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use serde::{
@@ -28,11 +28,21 @@ static GENKIT_DATA: OnceCell<RwLock<GenkitData>> = OnceCell::new();
 static DIRTY: AtomicBool = AtomicBool::new(false);
 static DATA_FILENAME: OnceCell<&str> = OnceCell::new();
 static MARKDOWN_VISITOR: OnceCell<Box<dyn MarkdownVisitor + Send + Sync>> = OnceCell::new();
+static MARKDOWN_CONFIG_OVERRIDE: OnceCell<MarkdownConfigOverride> = OnceCell::new();
+static PREVIEW_IMAGE_DIR: OnceCell<&str> = OnceCell::new();
+// Whether delete the data file if it becomes empty.
+static PRUNE_EMPTY: AtomicBool = AtomicBool::new(false);
+
+// The max size of the url preview image to cache.
+const MAX_PREVIEW_IMAGE_SIZE: usize = 5 * 1024 * 1024;
 
 pub(crate) fn load<P: AsRef<Path>>(path: P) {
-    GENKIT_DATA.get_or_init(|| {
-        RwLock::new(GenkitData::new(path.as_ref().join(get_data_filename())).unwrap())
-    });
+    GENKIT_DATA.get_or_init(|| RwLock::new(GenkitData::new(data_file(path)).unwrap()));
+}
+
+/// The path of the data file in the `root` directory.
+pub(crate) fn data_file<P: AsRef<Path>>(root: P) -> PathBuf {
+    root.as_ref().join(get_data_filename())
 }
 
 pub(crate) fn write() -> RwLockWriteGuard<'static, GenkitData> {
@@ -43,6 +53,14 @@ pub(crate) fn read() -> RwLockReadGuard<'static, GenkitData> {
     GENKIT_DATA.get().unwrap().read()
 }
 
+/// Clear the url sources of the `source` file if the data is loaded,
+/// see [`GenkitData::clear_url_sources`].
+pub(crate) fn clear_url_sources(source: &Path) {
+    if let Some(data) = GENKIT_DATA.get() {
+        data.read().clear_url_sources(source);
+    }
+}
+
 pub(crate) fn set_data_filename(filename: &'static str) {
     DATA_FILENAME.set(filename).unwrap();
 }
@@ -51,6 +69,20 @@ fn get_data_filename() -> &'static str {
     DATA_FILENAME.get().unwrap_or(&"genkit.json")
 }
 
+pub(crate) fn set_preview_image_dir(dir: &'static str) {
+    PREVIEW_IMAGE_DIR.set(dir.trim_matches('/')).unwrap();
+}
+
+/// The directory relative to the dest directory
+/// to cache the url preview images, `None` if disabled.
+pub(crate) fn get_preview_image_dir() -> Option<&'static str> {
+    PREVIEW_IMAGE_DIR.get().copied()
+}
+
+pub(crate) fn set_prune_empty(prune_empty: bool) {
+    PRUNE_EMPTY.store(prune_empty, Ordering::Relaxed);
+}
+
 pub(crate) fn set_markdown_visitor(visitor: Box<dyn MarkdownVisitor + Send + Sync>) {
     MARKDOWN_VISITOR.set(visitor).unwrap();
 }
@@ -59,16 +91,48 @@ pub(crate) fn get_markdown_visitor() -> Option<Box<dyn MarkdownVisitor + Send +
     MARKDOWN_VISITOR.get().map(|v| dyn_clone::clone_box(&**v))
 }
 
+pub(crate) fn set_markdown_config_override(config_override: MarkdownConfigOverride) {
+    MARKDOWN_CONFIG_OVERRIDE.set(config_override).unwrap();
+}
+
+/// The markdown config fields overridden by the command line,
+/// which have higher priority than the generator provided config.
+#[derive(Debug, Default)]
+pub(crate) struct MarkdownConfigOverride {
+    pub highlight_code: Option<bool>,
+    pub highlight_theme: Option<String>,
+}
+
+impl MarkdownConfigOverride {
+    pub(crate) fn apply(&self, config: &mut MarkdownConfig) {
+        if let Some(highlight_code) = self.highlight_code {
+            config.highlight_code = highlight_code;
+        }
+        if let Some(highlight_theme) = self.highlight_theme.as_ref() {
+            config.highlight_theme = highlight_theme.clone();
+        }
+    }
+}
+
 /// Export all data into the json file.
-/// If the data is empty, we never create the json file.
+/// If the data is empty, we never create the json file, the existing file
+/// is rewritten, or deleted if `prune_empty` is set.
 pub(crate) fn export<P: AsRef<Path>>(path: P) -> Result<()> {
     // Prevent repeatedly exporting the same data.
     // Otherwise will cause infinity auto reload.
     if DIRTY.load(Ordering::Relaxed) {
         let data = read();
-        if !data.url_previews.is_empty() {
-            let mut file = File::create(path.as_ref().join(get_data_filename()))?;
-            file.write_all(data.export_to_json()?.as_bytes())?;
+        let path = data_file(path);
+        if !data.url_previews.is_empty() || (path.exists() && !PRUNE_EMPTY.load(Ordering::Relaxed))
+        {
+            let json = data.export_to_json()?;
+            // The unchanged file isn't written, the write would trigger the watcher.
+            if fs::read_to_string(&path).ok().as_deref() != Some(json.as_str()) {
+                let mut file = File::create(&path)?;
+                file.write_all(json.as_bytes())?;
+            }
+        } else if path.exists() {
+            fs::remove_file(&path)?;
         }
         DIRTY.store(false, Ordering::Relaxed);
     }
@@ -137,6 +201,12 @@ impl<'de> de::Visitor<'de> for UrlPreviewInfoVisitor {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GenkitData {
+    // The root directory of the data file.
+    #[serde(skip)]
+    root: PathBuf,
+    // The dest directory of the current build to cache the preview images.
+    #[serde(skip)]
+    dest: Option<PathBuf>,
     #[serde(skip)]
     markdown_config: MarkdownConfig,
     // The preview tasks.
@@ -144,6 +214,12 @@ pub struct GenkitData {
     preview_tasks: DashMap<String, Receiver<Option<PreviewEvent>>>,
     // All url preview data.
     url_previews: Arc<DashMap<String, UrlPreviewInfo>>,
+    // The sources where each url comes from.
+    #[serde(default)]
+    url_sources: Arc<DashMap<String, BTreeSet<UrlSource>>>,
+    // The urls rendered in the current build.
+    #[serde(skip)]
+    rendered_urls: DashSet<String>,
 }
 
 // Implement Serialize manually to keep urlPreviews ordered.
@@ -158,12 +234,85 @@ impl Serialize for GenkitData {
             url_previews.insert(key.to_owned(), value.to_owned());
         });
 
-        let mut map = serializer.serialize_map(Some(1))?;
+        let mut url_sources = BTreeMap::new();
+        self.url_sources.iter().for_each(|kv| {
+            let (key, value) = kv.pair();
+            url_sources.insert(key.to_owned(), value.to_owned());
+        });
+
+        let mut map = serializer.serialize_map(Some(2))?;
         map.serialize_entry("urlPreviews", &url_previews)?;
+        map.serialize_entry("urlSources", &url_sources)?;
         map.end()
     }
 }
 
+/// The source where an url comes from.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct UrlSource {
+    /// The source file path relative to the root directory.
+    pub file: String,
+    /// The line number (1-based) in the source file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+}
+
+impl std::fmt::Display for UrlSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}", self.file, line),
+            None => write!(f, "{}", self.file),
+        }
+    }
+}
+
+/// Preview the url and wait until finished, return the cached preview if exists.
+///
+/// This is for the blocking build thread, so that a single build renders
+/// the complete preview cards. Must not be called in an async context.
+#[tokio::main(flavor = "current_thread")]
+pub(crate) async fn preview_url_blocking(url: &str) -> Result<UrlPreviewInfo> {
+    let (first_preview, mut rx) = {
+        // parking_lot RwLock guard isn't async-aware,
+        // we should keep this guard drop in this scope.
+        let data = read();
+        if let Some(info) = data.get_preview(url) {
+            return Ok(info);
+        }
+        data.preview_url(url)
+    };
+    rx.changed().await?;
+    let event = rx.borrow().clone();
+    match event {
+        Some(PreviewEvent::Finished(info)) => {
+            if first_preview {
+                println!("URL previewed: {url}");
+            }
+            Ok(info)
+        }
+        Some(PreviewEvent::Failed(err)) => anyhow::bail!(err),
+        None => anyhow::bail!("Url preview didn't initialized."),
+    }
+}
+
+// Download the preview image into the `dir` of `dest` under the content-hashed
+// name, return the local url of the image.
+async fn cache_preview_image(dest: &Path, dir: &str, url: &str) -> Result<String> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        anyhow::bail!("The preview image `{url}` isn't a remote url");
+    }
+
+    let (content, extension) = helpers::fetch_image(url, MAX_PREVIEW_IMAGE_SIZE).await?;
+    let filename = format!("{}.{extension}", helpers::hash_content(&content));
+    let cache_dir = dest.join(dir);
+    fs::create_dir_all(&cache_dir)?;
+    let file = cache_dir.join(&filename);
+    if !file.exists() {
+        fs::write(file, content)?;
+    }
+    Ok(format!("/{dir}/{filename}"))
+}
+
 #[derive(Debug, Clone)]
 pub enum PreviewEvent {
     Finished(UrlPreviewInfo),
@@ -173,14 +322,21 @@ pub enum PreviewEvent {
 impl GenkitData {
     pub(crate) fn new(source: impl AsRef<Path>) -> Result<Self> {
         let path = source.as_ref();
+        let root = path.parent().map(Path::to_path_buf).unwrap_or_default();
         if path.exists() {
             let json = fs::read_to_string(path)?;
-            Ok(serde_json::from_str(&json)?)
+            let mut data: GenkitData = serde_json::from_str(&json)?;
+            data.root = root;
+            Ok(data)
         } else {
             Ok(GenkitData {
+                root,
+                dest: None,
                 markdown_config: MarkdownConfig::default(),
                 url_previews: Arc::new(DashMap::default()),
                 preview_tasks: DashMap::default(),
+                url_sources: Arc::new(DashMap::default()),
+                rendered_urls: DashSet::default(),
             })
         }
     }
@@ -189,6 +345,112 @@ impl GenkitData {
         Arc::clone(&self.url_previews)
     }
 
+    pub(crate) fn get_all_url_sources(&self) -> Arc<DashMap<String, BTreeSet<UrlSource>>> {
+        Arc::clone(&self.url_sources)
+    }
+
+    // The `source` path relative to the root directory, joined by `/`.
+    fn relative_file(&self, source: &Path) -> String {
+        let source = source.strip_prefix(&self.root).unwrap_or(source);
+        source
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Record the `source` file and `line` where the `url` comes from.
+    pub(crate) fn record_url_source(&self, url: &str, source: &Path, line: Option<usize>) {
+        let file = self.relative_file(source);
+        let source = UrlSource { file, line };
+        if self
+            .url_sources
+            .entry(url.to_owned())
+            .or_default()
+            .insert(source)
+        {
+            DIRTY.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Remove all sources recorded from the `source` file, which is called
+    /// before the file is rendered again, so the moved or deleted links
+    /// never leave stale sources.
+    pub(crate) fn clear_url_sources(&self, source: &Path) {
+        let file = self.relative_file(source);
+        let mut removed = false;
+        self.url_sources.retain(|_, sources| {
+            let len = sources.len();
+            sources.retain(|source| source.file != file);
+            removed |= sources.len() != len;
+            !sources.is_empty()
+        });
+        if removed {
+            DIRTY.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Set the dest directory of the current build, and remove the previews
+    /// whose cached image is missing in the dest, such as the fresh dest of
+    /// the `serve` command, so that they are previewed and cached again.
+    pub(crate) fn set_dest(&mut self, dest: &Path) {
+        if let Some(dir) = get_preview_image_dir() {
+            let prefix = format!("/{dir}/");
+            let missing = self
+                .url_previews
+                .iter()
+                .filter(|kv| {
+                    kv.image
+                        .as_ref()
+                        .and_then(|image| image.strip_prefix(&prefix))
+                        .is_some_and(|file| !dest.join(dir).join(file).exists())
+                })
+                .map(|kv| kv.key().to_owned())
+                .collect::<Vec<_>>();
+            for url in missing {
+                self.url_previews.remove(&url);
+                self.preview_tasks.remove(&url);
+                DIRTY.store(true, Ordering::Relaxed);
+            }
+        }
+        self.dest = Some(dest.to_path_buf());
+    }
+
+    /// Mark the `url` rendered in the current build, see [`Self::prune_unrendered`].
+    pub(crate) fn mark_rendered(&self, url: &str) {
+        self.rendered_urls.insert(url.to_owned());
+    }
+
+    // Start tracking the rendered urls of a new build.
+    pub(crate) fn begin_build(&self) {
+        self.rendered_urls.clear();
+    }
+
+    /// Remove the previews and sources of the urls not rendered in the
+    /// current build, such as the links deleted from the markdown.
+    pub(crate) fn prune_unrendered(&self) {
+        let stale = self
+            .url_previews
+            .iter()
+            .map(|kv| kv.key().to_owned())
+            .chain(self.url_sources.iter().map(|kv| kv.key().to_owned()))
+            .filter(|url| !self.rendered_urls.contains(url))
+            .collect::<BTreeSet<_>>();
+        for url in stale {
+            self.remove_preview(&url);
+        }
+    }
+
+    /// Remove the preview and the sources of the `url`.
+    pub(crate) fn remove_preview(&self, url: &str) {
+        let removed_preview = self.url_previews.remove(url).is_some();
+        let removed_sources = self.url_sources.remove(url).is_some();
+        self.preview_tasks.remove(url);
+        if removed_preview || removed_sources {
+            DIRTY.store(true, Ordering::Relaxed);
+        }
+    }
+
     pub(crate) fn get_preview(&self, url: &str) -> Option<UrlPreviewInfo> {
         match self.url_previews.try_get(url) {
             TryResult::Present(info) => Some(info.to_owned()),
@@ -213,16 +475,26 @@ impl GenkitData {
 
             let url = url.to_owned();
             let list = Arc::clone(&self.url_previews);
+            let dest = self.dest.clone();
             // Spawn a background task to preview the url.
             tokio::spawn(async move {
                 match helpers::fetch_url(&url).await {
                     Ok(html) => {
                         let meta = html::parse_html_meta(html);
-                        let info = UrlPreviewInfo {
+                        let mut info = UrlPreviewInfo {
                             title: meta.title.into_owned(),
                             description: meta.description.into_owned(),
                             image: meta.image.as_ref().map(|image| image.to_string()),
                         };
+                        if let (Some(dir), Some(dest), Some(image)) =
+                            (get_preview_image_dir(), dest, info.image.clone())
+                        {
+                            match cache_preview_image(&dest, dir, &image).await {
+                                Ok(local_url) => info.image = Some(local_url),
+                                // Keep the remote image url as the fallback.
+                                Err(err) => println!("Warning: {err}"),
+                            }
+                        }
 
                         list.insert(url, info.clone());
                         DIRTY.store(true, Ordering::Relaxed);
@@ -235,7 +507,10 @@ impl GenkitData {
         }
     }
 
-    pub(crate) fn set_markdown_config(&mut self, config: MarkdownConfig) -> &mut Self {
+    pub(crate) fn set_markdown_config(&mut self, mut config: MarkdownConfig) -> &mut Self {
+        if let Some(config_override) = MARKDOWN_CONFIG_OVERRIDE.get() {
+            config_override.apply(&mut config);
+        }
         self.markdown_config = config;
         self
     }
@@ -248,3 +523,56 @@ impl GenkitData {
         Ok(serde_json::to_string_pretty(self)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{GenkitData, UrlPreviewInfo};
+
+    #[test]
+    fn test_prune_unrendered() {
+        let data = GenkitData::new("not-exists/genkit.json").unwrap();
+        for url in ["https://a.com", "https://b.com"] {
+            let info = UrlPreviewInfo {
+                title: url.to_owned(),
+                description: String::new(),
+                image: None,
+            };
+            data.url_previews.insert(url.to_owned(), info);
+            data.record_url_source(url, Path::new("not-exists/a.md"), Some(1));
+        }
+
+        data.begin_build();
+        data.mark_rendered("https://a.com");
+        data.prune_unrendered();
+        assert!(data.get_preview("https://a.com").is_some());
+        assert!(data.get_preview("https://b.com").is_none());
+        assert!(data.url_sources.contains_key("https://a.com"));
+        assert!(!data.url_sources.contains_key("https://b.com"));
+    }
+
+    #[test]
+    fn test_clear_url_sources() {
+        let data = GenkitData::new("not-exists/genkit.json").unwrap();
+        let (a, b) = (Path::new("not-exists/a.md"), Path::new("not-exists/b.md"));
+        data.record_url_source("https://a.com", a, Some(1));
+        data.record_url_source("https://b.com", a, Some(2));
+        data.record_url_source("https://b.com", b, Some(3));
+
+        // Re-render `a.md`, the `https://a.com` link is moved to line 5
+        // and the `https://b.com` link is deleted.
+        data.clear_url_sources(a);
+        data.record_url_source("https://a.com", a, Some(5));
+        let sources = |url: &str| {
+            data.url_sources
+                .get(url)
+                .map(|sources| sources.iter().map(ToString::to_string).collect::<Vec<_>>())
+        };
+        assert_eq!(sources("https://a.com"), Some(vec!["a.md:5".to_owned()]));
+        assert_eq!(sources("https://b.com"), Some(vec!["b.md:3".to_owned()]));
+
+        data.clear_url_sources(b);
+        assert_eq!(sources("https://b.com"), None);
+    }
+}
@@ -0,0 +1,118 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::Result;
+use parking_lot::RwLock;
+
+// The `(name, source)` pairs of the templates embedded into the binary,
+// in the order of registration.
+static EMBEDDED_TEMPLATES: RwLock<Vec<(&'static str, &'static str)>> =
+    parking_lot::const_rwlock(Vec::new());
+// The static assets embedded into the binary, keyed by the path relative to dest.
+static EMBEDDED_ASSETS: RwLock<Option<HashMap<&'static str, &'static [u8]>>> =
+    parking_lot::const_rwlock(None);
+
+pub(crate) fn add_templates<I>(templates: I)
+where
+    I: IntoIterator<Item = (&'static str, &'static str)>,
+{
+    EMBEDDED_TEMPLATES.write().extend(templates);
+}
+
+pub(crate) fn get_templates() -> Vec<(&'static str, &'static str)> {
+    EMBEDDED_TEMPLATES.read().clone()
+}
+
+pub(crate) fn add_assets<I>(assets: I)
+where
+    I: IntoIterator<Item = (&'static str, &'static [u8])>,
+{
+    EMBEDDED_ASSETS
+        .write()
+        .get_or_insert_with(HashMap::new)
+        .extend(
+            assets
+                .into_iter()
+                .map(|(path, content)| (path.trim_start_matches('/'), content)),
+        );
+}
+
+/// Write the embedded assets into the `dest` directory, the existing
+/// files (such as the static files copied by the generator) are kept.
+pub(crate) fn write_assets(dest: &Path) -> Result<()> {
+    let assets = EMBEDDED_ASSETS.read();
+    for (path, content) in assets.iter().flatten() {
+        let file = dest.join(path);
+        if file.exists() {
+            continue;
+        }
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(file, content)?;
+    }
+    Ok(())
+}
+
+/// Get the embedded asset by the request path.
+pub(crate) fn get_asset(path: &str) -> Option<&'static [u8]> {
+    let path = path.trim_start_matches('/');
+    let assets = EMBEDDED_ASSETS.read();
+    let assets = assets.as_ref()?;
+    if path.is_empty() || path.ends_with('/') {
+        assets.get(format!("{path}index.html").as_str()).copied()
+    } else {
+        assets.get(path).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::{add_assets, add_templates, get_asset, write_assets};
+    use crate::jinja::init_environment;
+
+    #[test]
+    fn test_get_asset() {
+        add_assets([
+            ("/test-embed/index.html", b"index".as_slice()),
+            ("test-embed/app.css", b"css".as_slice()),
+        ]);
+        assert_eq!(get_asset("/test-embed/"), Some(b"index".as_slice()));
+        assert_eq!(get_asset("test-embed/"), Some(b"index".as_slice()));
+        assert_eq!(get_asset("/test-embed/app.css"), Some(b"css".as_slice()));
+        assert_eq!(get_asset("/test-embed"), None);
+        assert_eq!(get_asset("/test-embed/missing.css"), None);
+
+        let dest = env::temp_dir().join(format!("genkit-embed-{}", std::process::id()));
+        fs::create_dir_all(dest.join("test-embed")).unwrap();
+        fs::write(dest.join("test-embed/app.css"), "on disk").unwrap();
+        write_assets(&dest).unwrap();
+        assert_eq!(
+            fs::read(dest.join("test-embed/index.html")).unwrap(),
+            b"index"
+        );
+        assert_eq!(
+            fs::read(dest.join("test-embed/app.css")).unwrap(),
+            b"on disk"
+        );
+        fs::remove_dir_all(dest).unwrap();
+    }
+
+    #[test]
+    fn test_override_embedded_template() {
+        add_templates([("test-embed.jinja", "embedded")]);
+        let mut env = init_environment();
+        let render = |env: &minijinja::Environment| {
+            env.get_template("test-embed.jinja")
+                .unwrap()
+                .render(())
+                .unwrap()
+        };
+        assert_eq!(render(&env), "embedded");
+
+        // The template registered later, such as on disk, overrides it.
+        env.add_template("test-embed.jinja", "on disk").unwrap();
+        assert_eq!(render(&env), "on disk");
+    }
+}
@@ -1,12 +1,27 @@
 use std::{
     fs,
     path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
-use crate::{context::Context, data, jinja::init_environment, Entity, Generator};
+use crate::{
+    context, data, embed, entity, image,
+    jinja::{self, init_environment},
+    output,
+    profile::Profiler,
+    Entity, Generator,
+};
 
 use anyhow::Result;
 
+/// The report of a finished build.
+#[derive(Debug)]
+pub(crate) struct BuildReport {
+    // The number of pages written by `output`.
+    pub pages_written: usize,
+    pub duration: Duration,
+}
+
 #[derive(Debug)]
 pub(crate) struct GenkitEngine<G> {
     source: PathBuf,
@@ -30,8 +45,9 @@ where
         })
     }
 
-    pub fn build(&mut self, reload: bool) -> Result<()> {
-        let instant = std::time::Instant::now();
+    pub fn build(&mut self, reload: bool) -> Result<BuildReport> {
+        let instant = Instant::now();
+        let mut profiler = Profiler::new();
         let source = self.source.as_ref();
         let dest = self.dest.as_ref();
         let mut entity = if reload {
@@ -39,26 +55,57 @@ where
         } else {
             self.generator.on_load(source)?
         };
+        profiler.phase("load");
 
         entity.parse(source)?;
+        profiler.phase("parse");
+
+        entity.validate(source)?;
+        profiler.phase("validate");
 
         let env = self
             .generator
             .on_extend_environment(source, init_environment(), &entity);
+        jinja::set_markdown_environment(env.clone());
 
-        if let Some(markdown_config) = self.generator.get_markdown_config(&entity) {
-            let mut guard = data::write();
-            guard.set_markdown_config(markdown_config);
-        }
+        let markdown_config = self
+            .generator
+            .get_markdown_config(&entity)
+            .unwrap_or_default();
+        data::write()
+            .set_markdown_config(markdown_config)
+            .set_dest(dest);
+        profiler.phase("env setup");
 
-        let context = Context::new();
-        entity
-            .render(&env, context.clone(), dest)
-            .expect("Render failed.");
+        image::set_build_dirs(source, dest);
+        output::clear_generated_pages();
+        // The global context is the base, the entities can override the values.
+        let context = context::global_context();
+        data::read().begin_build();
+        entity.render(&env, context.clone(), dest)?;
+        image::process_resize_jobs()?;
+        profiler.phase("render");
 
         self.generator
             .on_render(&env, context, &entity, source, dest)?;
-        println!("Build cost: {}ms", instant.elapsed().as_millis());
-        Ok(())
+        // Prune after `on_render`, which may render the urls too.
+        // The partial build doesn't render all urls.
+        if !entity::has_only_filter() {
+            data::read().prune_unrendered();
+        }
+        // The generator's output wins over the embedded assets.
+        embed::write_assets(dest)?;
+        profiler.phase("on_render");
+
+        let pages = output::take_generated_pages(dest);
+        self.generator.on_finish(&pages, dest)?;
+        profiler.phase("on_finish");
+        let duration = instant.elapsed();
+        println!("Build cost: {}ms", duration.as_millis());
+        profiler.print();
+        Ok(BuildReport {
+            pages_written: pages.len(),
+            duration,
+        })
     }
 }
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -7,6 +9,56 @@ pub struct MarkdownConfig {
     pub highlight_code: bool,
     #[serde(default = "MarkdownConfig::default_highlight_theme")]
     pub highlight_theme: String,
+    /// The base url prepended to the markdown image url, such as an image CDN.
+    #[serde(default)]
+    pub image_base_url: Option<String>,
+    /// Whether transform the external image url and data URI,
+    /// default to false.
+    #[serde(default)]
+    pub transform_external_images: bool,
+    /// The target widths to generate responsive images for local images,
+    /// default to empty which means disabled.
+    #[serde(default)]
+    pub responsive_image_widths: Vec<u32>,
+    /// Whether convert the bare urls in text into links, default to false.
+    #[serde(default)]
+    pub autolink: bool,
+    /// Whether shift all content headings down by one level (h1 to h2, etc),
+    /// clamped at h6. The page template usually owns the single h1.
+    #[serde(default)]
+    pub shift_headings: bool,
+    /// Whether print a warning if the content uses h1.
+    #[serde(default)]
+    pub warn_on_h1: bool,
+    /// Whether render the `diff` and `patch` code blocks with line classes
+    /// (`diff-add`, `diff-remove`, `diff-hunk`, `diff-header`) instead of
+    /// syntax highlighting, default to false.
+    #[serde(default)]
+    pub diff_line_classes: bool,
+    /// The `loading` attribute of the markdown images, default to `lazy`.
+    /// Set `None` to omit the attribute.
+    #[serde(default = "MarkdownConfig::default_image_loading")]
+    pub image_loading: Option<String>,
+    /// The `decoding` attribute of the markdown images, default to `async`.
+    /// Set `None` to omit the attribute.
+    #[serde(default = "MarkdownConfig::default_image_decoding")]
+    pub image_decoding: Option<String>,
+    /// The `fetchpriority` attribute of the markdown images, such as `high`
+    /// for the LCP image, default to `None` which omits the attribute.
+    #[serde(default)]
+    pub image_fetchpriority: Option<String>,
+    /// The glossary of the abbreviations, the whole-word terms in the text are
+    /// wrapped in `<abbr title="definition">`, except in the code, headings and links.
+    #[serde(default)]
+    pub abbreviations: BTreeMap<String, String>,
+    /// Whether only wrap the first occurrence of each term per page.
+    #[serde(default)]
+    pub abbreviations_first_only: bool,
+    /// The heading levels (after `shift_headings`) which get the auto ids and
+    /// anchor links, default to all levels. The other headings have no id
+    /// unless explicitly specified, such as `## Title {#id}`.
+    #[serde(default = "MarkdownConfig::default_anchor_levels")]
+    pub anchor_levels: Vec<usize>,
 }
 
 impl Default for MarkdownConfig {
@@ -14,6 +66,19 @@ impl Default for MarkdownConfig {
         Self {
             highlight_code: true,
             highlight_theme: Self::default_highlight_theme(),
+            image_base_url: None,
+            transform_external_images: false,
+            responsive_image_widths: Vec::new(),
+            autolink: false,
+            shift_headings: false,
+            warn_on_h1: false,
+            diff_line_classes: false,
+            image_loading: Self::default_image_loading(),
+            image_decoding: Self::default_image_decoding(),
+            image_fetchpriority: None,
+            abbreviations: BTreeMap::new(),
+            abbreviations_first_only: false,
+            anchor_levels: Self::default_anchor_levels(),
         }
     }
 }
@@ -28,4 +93,16 @@ impl MarkdownConfig {
     fn default_highlight_code() -> bool {
         true
     }
+
+    fn default_image_loading() -> Option<String> {
+        Some("lazy".into())
+    }
+
+    fn default_image_decoding() -> Option<String> {
+        Some("async".into())
+    }
+
+    fn default_anchor_levels() -> Vec<usize> {
+        (1..=6).collect()
+    }
 }
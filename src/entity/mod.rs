@@ -1,5 +1,6 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use minijinja::Environment;
+use parking_lot::RwLock;
 use rayon::{
     iter::{IntoParallelRefMutIterator, ParallelIterator},
     prelude::IntoParallelRefIterator,
@@ -8,23 +9,67 @@ use std::path::Path;
 
 mod markdown;
 
-use crate::context::Context;
+use crate::{context::Context, helpers, profile};
+
+// The glob to filter the entities by source path, see `build --only`.
+static ONLY_FILTER: RwLock<Option<String>> = parking_lot::const_rwlock(None);
+
+pub(crate) fn set_only_filter(pattern: Option<String>) {
+    *ONLY_FILTER.write() = pattern;
+}
+
+// Whether only part of the entities are built by the `--only` filter.
+pub(crate) fn has_only_filter() -> bool {
+    ONLY_FILTER.read().is_some()
+}
+
+// Whether the entity should be built according to the excludes and the `--only`
+// filter. The entity without a source path is always built.
+fn is_selected<E: Entity>(entity: &E) -> bool {
+    if entity.source_path().is_some_and(helpers::is_excluded) {
+        return false;
+    }
+    match (ONLY_FILTER.read().as_ref(), entity.source_path()) {
+        (Some(pattern), Some(path)) => {
+            helpers::glob_match(pattern, &path.to_string_lossy().replace('\\', "/"))
+        }
+        _ => true,
+    }
+}
 
 pub use markdown::MarkdownConfig;
 
 /// A trait represents the entity of config file.
 ///
-/// An entity contains two stage:
+/// An entity contains three stage:
 /// - **parse**, the stage the entity to parse its attribute, such as parse markdown to html.
+/// - **validate**, the stage to check the invariants of the parsed entity.
 /// - **render**, the stage to render the entity to html file.
 ///
-/// [`Entity`] has default empty implementations for both methods.
+/// [`Entity`] has default empty implementations for all methods.
 #[allow(unused_variables)]
 pub trait Entity {
+    /// The source path of the entity relative to the source directory,
+    /// such as `posts/2024/hello.md`.
+    ///
+    /// The entities in a `Vec` not matching the `build --only` glob are
+    /// skipped to parse and render. Return `None` to always build the entity.
+    fn source_path(&self) -> Option<&Path> {
+        None
+    }
+
     fn parse(&mut self, source: &Path) -> Result<()> {
         Ok(())
     }
 
+    /// Check the invariants after all entities parsed and before any rendering,
+    /// such as the required front matter fields or the referenced images exist.
+    ///
+    /// The errors of all entities in a `Vec` are reported together.
+    fn validate(&self, source: &Path) -> Result<()> {
+        Ok(())
+    }
+
     fn render(&self, env: &Environment, context: Context, dest: &Path) -> Result<()> {
         Ok(())
     }
@@ -38,6 +83,13 @@ impl<T: Entity> Entity for Option<T> {
         Ok(())
     }
 
+    fn validate(&self, source: &Path) -> Result<()> {
+        if let Some(entity) = self {
+            entity.validate(source)?;
+        }
+        Ok(())
+    }
+
     fn render(&self, env: &Environment, context: Context, dest: &Path) -> Result<()> {
         if let Some(entity) = self {
             entity.render(env, context, dest)?;
@@ -49,13 +101,72 @@ impl<T: Entity> Entity for Option<T> {
 impl<T: Entity + Sync + Send + Clone + 'static> Entity for Vec<T> {
     fn parse(&mut self, source: &Path) -> Result<()> {
         self.par_iter_mut()
+            .filter(|entity| is_selected(*entity))
             .try_for_each(|entity| entity.parse(source))
     }
 
+    fn validate(&self, source: &Path) -> Result<()> {
+        let mut errors = self
+            .par_iter()
+            .filter(|entity| is_selected(*entity))
+            .filter_map(|entity| entity.validate(source).err())
+            .collect::<Vec<_>>();
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            count => {
+                let messages = errors
+                    .iter()
+                    .map(|err| format!("- {err:#}"))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                Err(anyhow!("{count} validation errors:\n{messages}"))
+            }
+        }
+    }
+
     fn render(&self, env: &Environment, context: Context, dest: &Path) -> Result<()> {
-        self.par_iter().try_for_each(|entity| {
-            let context = context.clone();
-            entity.render(env, context, dest)
-        })
+        self.par_iter()
+            .filter(|entity| is_selected(*entity))
+            .try_for_each(|entity| {
+                let context = context.clone();
+                profile::measure_entity(|| entity.render(env, context, dest))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use anyhow::{ensure, Result};
+
+    use super::Entity;
+
+    #[derive(Clone)]
+    struct Post(&'static str);
+
+    impl Entity for Post {
+        fn validate(&self, _source: &Path) -> Result<()> {
+            ensure!(!self.0.is_empty(), "Post title is required");
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_validate_collect_errors() {
+        let source = Path::new(".");
+        assert!(vec![Post("a"), Post("b")].validate(source).is_ok());
+
+        let err = vec![Post(""), Post("b")].validate(source).unwrap_err();
+        assert_eq!(err.to_string(), "Post title is required");
+
+        let err = vec![Post(""), Post("b"), Post("")]
+            .validate(source)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "2 validation errors:\n- Post title is required\n- Post title is required"
+        );
     }
 }
@@ -1,18 +1,40 @@
 use anyhow::Result;
 use bytes::Bytes;
-use http_body_util::{BodyExt, Empty};
-use hyper::{body::Buf, http::HeaderValue, Request, Uri};
+use http_body_util::{BodyExt, Empty, Limited};
+use hyper::{
+    body::{Buf, Incoming},
+    header::CONTENT_TYPE,
+    http::HeaderValue,
+    Request, Response, Uri,
+};
 use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+use parking_lot::RwLock;
 use rayon::iter::{ParallelBridge, ParallelIterator};
+use sha2::{Digest, Sha256};
 use std::{
     collections::HashMap,
     fs,
     io::{self, ErrorKind, Read},
-    path::Path,
+    path::{Component, Path},
     process::Command,
+    sync::atomic::{AtomicBool, Ordering},
 };
 use time::{format_description, Date};
 
+// The globs of the source paths excluded from the output, see `Genkit::exclude`.
+static EXCLUDE_GLOBS: RwLock<Vec<String>> = parking_lot::const_rwlock(Vec::new());
+
+// Whether exclude the paths with any name starting with `_`, see `Genkit::exclude_underscored`.
+static EXCLUDE_UNDERSCORED: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn add_exclude_glob(glob: String) {
+    EXCLUDE_GLOBS.write().push(glob);
+}
+
+pub(crate) fn set_exclude_underscored(exclude: bool) {
+    EXCLUDE_UNDERSCORED.store(exclude, Ordering::Relaxed);
+}
+
 pub fn run_command(program: &str, args: &[&str]) -> Result<String, io::Error> {
     let out = Command::new(program).args(args).output()?;
     match out.status.success() {
@@ -65,6 +87,40 @@ pub fn split_styles(style: &str) -> HashMap<&str, &str> {
 }
 
 pub async fn fetch_url(url: &str) -> Result<impl Read> {
+    let resp = send_request(url).await?;
+    let bytes = resp.into_body().collect().await?.to_bytes();
+    Ok(bytes.reader())
+}
+
+/// Fetch the image of the `url`, return the image content and the file
+/// extension guessed from the content type.
+///
+/// Fail if the content type isn't an image or the size exceeds `max_size`.
+/// The SVG image is rejected, as it can carry scripts.
+pub async fn fetch_image(url: &str, max_size: usize) -> Result<(Bytes, &'static str)> {
+    let resp = send_request(url).await?;
+    let content_type = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let extension = match content_type.split(';').next().unwrap_or_default().trim() {
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/avif" => "avif",
+        _ => anyhow::bail!("The content type `{content_type}` of `{url}` isn't an image"),
+    };
+    let bytes = Limited::new(resp.into_body(), max_size)
+        .collect()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to fetch image `{url}`: {err}"))?
+        .to_bytes();
+    Ok((bytes, extension))
+}
+
+async fn send_request(url: &str) -> Result<Response<Incoming>> {
     let client = Client::builder(TokioExecutor::new()).build_http();
     let mut req = Request::new(Empty::<Bytes>::new());
     *req.uri_mut() = url.parse::<Uri>()?;
@@ -93,13 +149,43 @@ pub async fn fetch_url(url: &str) -> Result<impl Read> {
         println!("{warning}");
         anyhow::bail!(warning);
     }
-    let bytes = resp.into_body().collect().await?.to_bytes();
-    Ok(bytes.reader())
+    Ok(resp)
+}
+
+/// Hash the content into a short hex string, mainly used to
+/// name the cached files.
+///
+/// The hash is the prefix of the SHA-256 digest, which is stable
+/// across the Rust releases, so the cached names never change.
+pub fn hash_content(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .take(8)
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
 }
 
 /// Copy directory recursively.
 /// Note: the empty directory is ignored.
 pub fn copy_dir(source: &Path, dest: &Path) -> Result<()> {
+    copy_dir_with(source, dest, &HashMap::new())
+}
+
+/// The processor to transform a file when copying, which receives the file path
+/// and content, returns the transformed content and the output filename.
+///
+/// The output filename must be a plain filename, such as `main.css`,
+/// the path separators and `..` are rejected.
+pub type CopyProcessor = Box<dyn Fn(&Path, Vec<u8>) -> Result<(Vec<u8>, String)> + Send + Sync>;
+
+/// Copy directory recursively, the files are transformed by the processor
+/// registered for their extension (such as `scss`), the others are copied verbatim.
+/// Note: the empty directory is ignored.
+pub fn copy_dir_with(
+    source: &Path,
+    dest: &Path,
+    processors: &HashMap<&str, CopyProcessor>,
+) -> Result<()> {
     let source_parent = source.parent().expect("Can not copy the root dir");
     walkdir::WalkDir::new(source)
         .into_iter()
@@ -121,7 +207,29 @@ pub fn copy_dir(source: &Path, dest: &Path) -> Result<()> {
                     }
                 }
                 let to = dest.join(path.strip_prefix(source_parent)?);
-                fs::copy(path, to)?;
+                let processor = path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .and_then(|ext| processors.get(ext));
+                match processor {
+                    Some(processor) => {
+                        let (content, filename) = processor(path, fs::read(path)?)?;
+                        let mut components = Path::new(&filename).components();
+                        if !matches!(
+                            (components.next(), components.next()),
+                            (Some(Component::Normal(_)), None)
+                        ) {
+                            anyhow::bail!(
+                                "Invalid output filename `{filename}` of `{}`, it should be a plain filename",
+                                path.display()
+                            );
+                        }
+                        fs::write(to.with_file_name(filename), content)?;
+                    }
+                    None => {
+                        fs::copy(path, to)?;
+                    }
+                }
             }
 
             anyhow::Ok(())
@@ -129,6 +237,74 @@ pub fn copy_dir(source: &Path, dest: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Match the `/` separated `path` against the glob `pattern`:
+///
+/// - `*` matches any characters except `/`.
+/// - `?` matches any single character except `/`.
+/// - `**` as a whole segment matches zero or more segments.
+///
+/// ```rust
+/// # use genkit::helpers::glob_match;
+/// assert!(glob_match("posts/2024/**", "posts/2024/01/hello.md"));
+/// assert!(!glob_match("posts/*.md", "posts/2024/hello.md"));
+/// ```
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    fn match_segments(pattern: &[&str], path: &[&str]) -> bool {
+        match pattern.split_first() {
+            None => path.is_empty(),
+            Some((&"**", rest)) => (0..=path.len()).any(|i| match_segments(rest, &path[i..])),
+            Some((segment, rest)) => match path.split_first() {
+                Some((name, path)) => {
+                    match_segment(segment.as_bytes(), name.as_bytes()) && match_segments(rest, path)
+                }
+                None => false,
+            },
+        }
+    }
+
+    fn match_segment(pattern: &[u8], name: &[u8]) -> bool {
+        match (pattern.split_first(), name.split_first()) {
+            (None, None) => true,
+            (Some((b'*', rest)), _) => (0..=name.len()).any(|i| match_segment(rest, &name[i..])),
+            (Some((b'?', rest)), Some((_, name))) => match_segment(rest, name),
+            (Some((p, rest)), Some((c, name))) if p == c => match_segment(rest, name),
+            _ => false,
+        }
+    }
+
+    let pattern = pattern.trim_matches('/').split('/').collect::<Vec<_>>();
+    let path = path.trim_matches('/').split('/').collect::<Vec<_>>();
+    match_segments(&pattern, &path)
+}
+
+/// Whether the source `path` (relative to the source directory) is excluded
+/// from the output, the generator should skip it when enumerating sources.
+///
+/// The paths matching the globs of `Genkit::exclude` are excluded. Nothing is
+/// excluded by default, the underscore convention is opt-in by
+/// `Genkit::exclude_underscored`, which excludes the path with any file or
+/// directory name starting with `_`, such as `_partials/nav.md` and `_drafts/hello.md`.
+///
+/// ```rust
+/// # use genkit::helpers::is_excluded;
+/// assert!(!is_excluded("_drafts/hello.md"));
+/// assert!(!is_excluded("posts/hello.md"));
+/// ```
+pub fn is_excluded(path: impl AsRef<Path>) -> bool {
+    is_excluded_by(
+        &EXCLUDE_GLOBS.read(),
+        EXCLUDE_UNDERSCORED.load(Ordering::Relaxed),
+        path.as_ref(),
+    )
+}
+
+fn is_excluded_by(globs: &[String], underscored: bool, path: &Path) -> bool {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let path = path.trim_start_matches("./");
+    (underscored && path.split('/').any(|name| name.starts_with('_')))
+        || globs.iter().any(|glob| glob_match(glob, path))
+}
+
 /// A serde module to serialize and deserialize [`time::Date`] type.
 pub mod serde_date {
     use super::*;
@@ -211,3 +387,108 @@ pub mod serde_date {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, env, fs};
+
+    use std::path::Path;
+
+    use super::{copy_dir_with, glob_match, hash_content, is_excluded_by, CopyProcessor};
+
+    #[test]
+    fn test_hash_content() {
+        // The prefix of the SHA-256 digest of the empty content.
+        assert_eq!(hash_content(b""), "e3b0c44298fc1c14");
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("posts/2024/**", "posts/2024/hello.md"));
+        assert!(glob_match("posts/2024/**", "posts/2024/01/hello.md"));
+        assert!(glob_match("**/*.md", "hello.md"));
+        assert!(glob_match("**/*.md", "posts/2024/hello.md"));
+        assert!(glob_match("posts/*.md", "/posts/hello.md"));
+        assert!(glob_match("posts/h?llo.*", "posts/hello.md"));
+        assert!(!glob_match("posts/*.md", "posts/2024/hello.md"));
+        assert!(!glob_match("posts/2024/**", "posts/2023/hello.md"));
+        assert!(!glob_match("*.md", "hello.html"));
+    }
+
+    #[test]
+    fn test_is_excluded() {
+        let globs = ["README.md".to_owned(), "**/*.draft.md".to_owned()];
+        assert!(is_excluded_by(&globs, true, Path::new("_partials/nav.md")));
+        assert!(is_excluded_by(
+            &globs,
+            true,
+            Path::new("./posts/_drafts/hello.md")
+        ));
+        assert!(is_excluded_by(&globs, false, Path::new("README.md")));
+        assert!(is_excluded_by(
+            &globs,
+            false,
+            Path::new("posts/hello.draft.md")
+        ));
+        assert!(!is_excluded_by(&globs, false, Path::new("posts/README.md")));
+        assert!(!is_excluded_by(
+            &globs,
+            true,
+            Path::new("posts/hello_world.md")
+        ));
+
+        // The underscore convention is opt-in.
+        assert!(!is_excluded_by(
+            &globs,
+            false,
+            Path::new("_partials/nav.md")
+        ));
+        assert!(!is_excluded_by(
+            &globs,
+            false,
+            Path::new("posts/_drafts/hello.md")
+        ));
+    }
+
+    #[test]
+    fn test_copy_dir_with() {
+        let root = env::temp_dir().join(format!("genkit-copy-dir-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let source = root.join("static");
+        fs::create_dir_all(source.join("css")).unwrap();
+        fs::write(source.join("css/main.scss"), "body {}").unwrap();
+        fs::write(source.join("logo.svg"), "<svg></svg>").unwrap();
+
+        let mut processors = HashMap::<&str, CopyProcessor>::new();
+        processors.insert(
+            "scss",
+            Box::new(|path, content| {
+                let filename = path.with_extension("css");
+                let filename = filename.file_name().unwrap().to_string_lossy();
+                Ok((content.to_ascii_uppercase(), filename.into_owned()))
+            }),
+        );
+        let dest = root.join("build");
+        copy_dir_with(&source, &dest, &processors).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dest.join("static/css/main.css")).unwrap(),
+            "BODY {}"
+        );
+        assert!(!dest.join("static/css/main.scss").exists());
+        assert_eq!(
+            fs::read_to_string(dest.join("static/logo.svg")).unwrap(),
+            "<svg></svg>"
+        );
+
+        for filename in ["../main.css", "css/main.css", "..", ""] {
+            let mut processors = HashMap::<&str, CopyProcessor>::new();
+            processors.insert(
+                "scss",
+                Box::new(move |_, content| Ok((content, filename.to_owned()))),
+            );
+            assert!(copy_dir_with(&source, &dest, &processors).is_err());
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -1,9 +1,10 @@
-use std::{borrow::Cow, io::Read};
+use std::{borrow::Cow, fmt::Write, io::Read};
 
 use html5ever::{
     parse_document, tendril::TendrilSink, tree_builder::TreeBuilderOpts, Attribute, ParseOpts,
 };
 use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use pulldown_cmark_escape::escape_html;
 
 use serde::Serialize;
 
@@ -21,10 +22,53 @@ impl<'a> Meta<'a> {
         self.title.to_mut().truncate(200);
         self.description.to_mut().truncate(200);
     }
+
+    /// Render the `<title>` and the standard OpenGraph and Twitter meta tags,
+    /// which is the inverse of [`parse_html_meta`].
+    pub fn to_html_tags(&self) -> String {
+        let mut html = String::new();
+        let mut tag = |format: &str, value: &str| {
+            let mut escaped = String::new();
+            escape_html(&mut escaped, value).unwrap();
+            writeln!(&mut html, "{}", format.replace("{}", &escaped)).unwrap();
+        };
+
+        tag("<title>{}</title>", &self.title);
+        tag(
+            r#"<meta name="description" content="{}">"#,
+            &self.description,
+        );
+        tag(r#"<meta property="og:title" content="{}">"#, &self.title);
+        tag(
+            r#"<meta property="og:description" content="{}">"#,
+            &self.description,
+        );
+        if let Some(url) = self.url.as_ref() {
+            tag(r#"<meta property="og:url" content="{}">"#, url);
+        }
+        if let Some(image) = self.image.as_ref() {
+            tag(r#"<meta property="og:image" content="{}">"#, image);
+        }
+
+        let card = if self.image.is_some() {
+            "summary_large_image"
+        } else {
+            "summary"
+        };
+        tag(r#"<meta name="twitter:card" content="{}">"#, card);
+        tag(r#"<meta name="twitter:title" content="{}">"#, &self.title);
+        tag(
+            r#"<meta name="twitter:description" content="{}">"#,
+            &self.description,
+        );
+        if let Some(image) = self.image.as_ref() {
+            tag(r#"<meta name="twitter:image" content="{}">"#, image);
+        }
+        html
+    }
 }
 
-/// Parse HTML [`Meta`] from `html`.
-pub fn parse_html_meta<'a, R: Read>(mut html: R) -> Meta<'a> {
+fn parse_dom<R: Read>(mut html: R) -> RcDom {
     let parse_opts = ParseOpts {
         tree_builder: TreeBuilderOpts {
             scripting_enabled: false,
@@ -34,10 +78,89 @@ pub fn parse_html_meta<'a, R: Read>(mut html: R) -> Meta<'a> {
         ..Default::default()
     };
 
-    let rc_dom = parse_document(RcDom::default(), parse_opts)
+    parse_document(RcDom::default(), parse_opts)
         .from_utf8()
         .read_from(&mut html)
-        .unwrap();
+        .unwrap()
+}
+
+/// Parse the `href` of all `<a>` tags from `html`.
+pub fn parse_html_links<R: Read>(html: R) -> Vec<String> {
+    fn collect(handle: &Handle, links: &mut Vec<String>) {
+        if let NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } = &handle.data
+        {
+            if name.local.as_ref() == "a" {
+                if let Some(href) = attrs
+                    .borrow()
+                    .iter()
+                    .find(|attr| attr.name.local.as_ref() == "href")
+                {
+                    links.push(href.value.trim().to_owned());
+                }
+            }
+        }
+        for child in handle.children.borrow().iter() {
+            collect(child, links);
+        }
+    }
+
+    let mut links = Vec::new();
+    collect(&parse_dom(html).document, &mut links);
+    links
+}
+
+/// Extract the plain text from `html` with the whitespaces collapsed,
+/// such as for the search index. The text of `<main>` is preferred
+/// if exists, the `<head>`, `<nav>`, `<script>` and `<style>` are ignored.
+pub fn extract_html_text<R: Read>(html: R) -> String {
+    const IGNORED_TAGS: [&str; 6] = ["head", "nav", "script", "style", "noscript", "template"];
+    const INLINE_TAGS: [&str; 12] = [
+        "a", "abbr", "b", "code", "em", "i", "kbd", "mark", "small", "span", "strong", "sub",
+    ];
+
+    fn find_main(handle: &Handle) -> Option<Handle> {
+        if let NodeData::Element { ref name, .. } = handle.data {
+            if name.local.as_ref() == "main" {
+                return Some(handle.clone());
+            }
+        }
+        handle.children.borrow().iter().find_map(find_main)
+    }
+
+    fn collect(handle: &Handle, text: &mut String) {
+        match handle.data {
+            NodeData::Text { ref contents } => {
+                text.push_str(&contents.borrow());
+                return;
+            }
+            NodeData::Element { ref name, .. } if IGNORED_TAGS.contains(&name.local.as_ref()) => {
+                return;
+            }
+            // Separate the text of the block elements.
+            NodeData::Element { ref name, .. } if !INLINE_TAGS.contains(&name.local.as_ref()) => {
+                text.push(' ')
+            }
+            _ => {}
+        }
+        for child in handle.children.borrow().iter() {
+            collect(child, text);
+        }
+    }
+
+    let dom = parse_dom(html);
+    let root = find_main(&dom.document).unwrap_or(dom.document);
+    let mut text = String::new();
+    collect(&root, &mut text);
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Parse HTML [`Meta`] from `html`.
+pub fn parse_html_meta<'a, R: Read>(html: R) -> Meta<'a> {
+    let rc_dom = parse_dom(html);
 
     let mut meta = Meta::default();
     if let NodeData::Document = rc_dom.document.data {
@@ -159,7 +282,58 @@ fn walk(handle: &Handle, meta: &mut Meta, super_node: &str) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use super::parse_html_meta;
+    use super::{extract_html_text, parse_html_links, parse_html_meta, Meta};
+
+    #[test]
+    fn test_extract_html_text() {
+        let html = r#"<html><head><title>Title</title><style>p {}</style></head><body>
+            <nav>Home</nav>
+            <p>Hello <em>world</em>,</p><p>second   line</p>
+            <script>let a = 1;</script>
+        </body></html>"#;
+        assert_eq!(
+            extract_html_text(html.as_bytes()),
+            "Hello world, second line"
+        );
+
+        let html = "<body><header>Site</header><main><h1>Post</h1><p>Text</p></main></body>";
+        assert_eq!(extract_html_text(html.as_bytes()), "Post Text");
+    }
+
+    #[test]
+    fn test_parse_html_links() {
+        let html = r#"<html><body>
+            <a href="/about/">About</a>
+            <p><a href=" posts/hello.html#intro ">Hello</a><a>No href</a></p>
+        </body></html>"#;
+        assert_eq!(
+            parse_html_links(html.as_bytes()),
+            ["/about/", "posts/hello.html#intro"]
+        );
+    }
+
+    #[test]
+    fn test_meta_to_html_tags() {
+        let meta = Meta {
+            title: "Rust & \"Genkit\"".into(),
+            description: "A <generator> kit".into(),
+            url: Some("https://example.com/".into()),
+            image: Some("https://example.com/a.png".into()),
+        };
+        let tags = meta.to_html_tags();
+        assert!(tags.contains("<title>Rust &amp; &quot;Genkit&quot;</title>"));
+        assert!(
+            tags.contains(r#"<meta property="og:description" content="A &lt;generator&gt; kit">"#)
+        );
+        assert!(tags.contains(r#"<meta name="twitter:card" content="summary_large_image">"#));
+
+        // Parse the rendered tags should get the same meta.
+        let parsed = parse_html_meta(format!("<head>{tags}</head>").as_bytes());
+        assert_eq!(parsed.title, meta.title);
+        assert_eq!(parsed.description, meta.description);
+        assert_eq!(parsed.url, meta.url);
+        assert_eq!(parsed.image, meta.image);
+    }
 
     #[test]
     fn test_parse_html_meta1() {
@@ -0,0 +1,158 @@
+use std::{
+    collections::HashSet,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use image::imageops::FilterType;
+use parking_lot::{Mutex, RwLock};
+use pulldown_cmark_escape::escape_href;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::helpers;
+
+// The source and dest directory of the current build.
+static BUILD_DIRS: RwLock<Option<(PathBuf, PathBuf)>> = parking_lot::const_rwlock(None);
+// The pending resize jobs scheduled during markdown rendering.
+static RESIZE_JOBS: Mutex<Vec<ResizeJob>> = parking_lot::const_mutex(Vec::new());
+
+// A job to resize the source image into a variant.
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct ResizeJob {
+    source: PathBuf,
+    dest: PathBuf,
+    width: u32,
+}
+
+pub(crate) fn set_build_dirs(source: &Path, dest: &Path) {
+    *BUILD_DIRS.write() = Some((source.to_path_buf(), dest.to_path_buf()));
+}
+
+// Serialize the tests which set the global build dirs and resize jobs.
+#[cfg(test)]
+static BUILD_DIRS_LOCK: Mutex<()> = parking_lot::const_mutex(());
+
+/// Set the build dirs with the pending resize jobs cleared for the test,
+/// the other tests can't change them until the returned guard drops.
+#[cfg(test)]
+pub(crate) fn lock_build_dirs(source: &Path, dest: &Path) -> parking_lot::MutexGuard<'static, ()> {
+    let guard = BUILD_DIRS_LOCK.lock();
+    set_build_dirs(source, dest);
+    RESIZE_JOBS.lock().clear();
+    guard
+}
+
+/// Render the responsive `<picture>` markup for local image,
+/// and schedule the resize jobs of the variants.
+///
+/// The `attrs` are appended to the `<img>`, such as ` loading="lazy"`.
+/// The `transform_url` is applied to the image and variant urls before escaping,
+/// such as prepending the CDN base url.
+///
+/// Return `None` if the image is unsupported, such as remote image,
+/// SVG or the image file doesn't exist.
+pub(crate) fn render_responsive_image(
+    url: &str,
+    alt: &str,
+    title: &str,
+    attrs: &str,
+    widths: &[u32],
+    transform_url: impl Fn(&str) -> String,
+) -> Option<String> {
+    // Only the root-relative url can be resolved to the source file.
+    let path = url.strip_prefix('/')?;
+    let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+    if !matches!(extension.as_str(), "png" | "jpg" | "jpeg" | "gif" | "webp") {
+        return None;
+    }
+
+    let (source, dest) = BUILD_DIRS.read().clone()?;
+    let source_file = source.join(path);
+    let (original_width, _) = image::image_dimensions(&source_file).ok()?;
+    // Never upscale the image.
+    let widths = widths
+        .iter()
+        .copied()
+        .filter(|width| *width < original_width)
+        .collect::<Vec<_>>();
+    if widths.is_empty() {
+        return None;
+    }
+
+    // Key the variants by the source hash, so an unchanged image
+    // never regenerates its variants.
+    let hash = helpers::hash_content(&fs::read(&source_file).ok()?);
+    let (stem, _) = path.rsplit_once('.')?;
+    let variant_url = |width: u32, extension: &str| format!("/{stem}-{hash}-{width}w.{extension}");
+    // The comma is encoded as well, otherwise it splits the srcset candidate.
+    let escape_url = |url: &str| {
+        let mut escaped = String::new();
+        escape_href(&mut escaped, &transform_url(url)).unwrap();
+        escaped.replace(',', "%2C")
+    };
+
+    let mut jobs = RESIZE_JOBS.lock();
+    let mut webp_srcset = Vec::new();
+    let mut srcset = Vec::new();
+    for &width in &widths {
+        for (format, set) in [
+            ("webp", &mut webp_srcset),
+            (extension.as_str(), &mut srcset),
+        ] {
+            let url = variant_url(width, format);
+            set.push(format!("{} {width}w", escape_url(&url)));
+            jobs.push(ResizeJob {
+                source: source_file.clone(),
+                dest: dest.join(url.trim_start_matches('/')),
+                width,
+            });
+        }
+    }
+    let src = escape_url(url);
+    srcset.push(format!("{src} {original_width}w"));
+
+    let mut html = String::new();
+    writeln!(&mut html, "<picture>").ok()?;
+    writeln!(
+        &mut html,
+        r#" <source type="image/webp" srcset="{}">"#,
+        webp_srcset.join(", ")
+    )
+    .ok()?;
+    writeln!(
+        &mut html,
+        r#" <img src="{src}" srcset="{}" alt="{alt}" title="{title}"{attrs}>"#,
+        srcset.join(", ")
+    )
+    .ok()?;
+    write!(&mut html, "</picture>").ok()?;
+    Some(html)
+}
+
+/// Generate all scheduled image variants in parallel.
+///
+/// The variant which already exists in dest is skipped.
+pub(crate) fn process_resize_jobs() -> Result<()> {
+    let jobs = std::mem::take(&mut *RESIZE_JOBS.lock())
+        .into_iter()
+        .filter(|job| !job.dest.exists())
+        .collect::<HashSet<_>>();
+
+    jobs.into_par_iter().try_for_each(|job| {
+        let image = image::open(&job.source)?;
+        let height = job.width * image.height() / image.width();
+        if let Some(parent) = job.dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let variant = image.resize(job.width, height, FilterType::Lanczos3);
+        if job.dest.extension().is_some_and(|ext| ext == "webp") {
+            // The webp encoder only supports 8-bit color.
+            variant.to_rgba8().save(&job.dest)?;
+        } else {
+            variant.save(&job.dest)?;
+        }
+        anyhow::Ok(())
+    })
+}
@@ -1,6 +1,37 @@
-use crate::markdown::{self, _render_html};
+use crate::{
+    context, embed,
+    markdown::{self, _render_html},
+};
 
-use minijinja::Environment;
+use std::cmp::Ordering;
+
+use minijinja::{context, Environment, Error, ErrorKind, Value};
+use parking_lot::RwLock;
+use time::{format_description, OffsetDateTime, UtcOffset};
+
+// The offset applied to the `now_local` function, `None` means UTC.
+static UTC_OFFSET: RwLock<Option<UtcOffset>> = parking_lot::const_rwlock(None);
+
+pub(crate) fn set_utc_offset(offset: UtcOffset) {
+    *UTC_OFFSET.write() = Some(offset);
+}
+
+// The environment extended by the generator in the latest build, so the user
+// overrides of the `__genkit_*` templates take effect in the markdown rendering.
+static MARKDOWN_ENVIRONMENT: RwLock<Option<Environment<'static>>> = parking_lot::const_rwlock(None);
+
+pub(crate) fn set_markdown_environment(env: Environment<'static>) {
+    *MARKDOWN_ENVIRONMENT.write() = Some(env);
+}
+
+// The environment to render the markdown templates, such as the heading
+// and the code blocks. Fallback to the fundamental environment.
+pub(crate) fn markdown_environment() -> Environment<'static> {
+    MARKDOWN_ENVIRONMENT
+        .read()
+        .clone()
+        .unwrap_or_else(init_environment)
+}
 
 // The `Environment` only includes the fundamental functions and filters.
 // It is used for rendering html in some code blocks, such as `QuoteBlock`.
@@ -19,25 +50,202 @@ pub fn init_environment<'a>() -> Environment<'a> {
     for (name, template) in templates {
         env.add_template(name, template).unwrap();
     }
+    for (name, template) in embed::get_templates() {
+        env.add_template(name, template)
+            .expect("Add embedded template failed.");
+    }
+
+    for (key, value) in context::global_context().iter() {
+        env.add_global(key.to_owned(), Value::from_serialize(value));
+    }
 
-    env.add_function("markdown_to_html", markdown::render_html);
+    env.add_function("markdown_to_html", markdown_to_html_function);
+    env.add_function("live_reload_script", live_reload_script_function);
     env.add_function("now", now_function);
+    env.add_function("now_local", now_local_function);
     env.add_filter("trim_start_matches", trim_start_matches_filter);
+    env.add_filter("group_by", group_by_filter);
+    env.add_filter("sort_by", sort_by_filter);
     env.add_function("markdown_to_rss", markdown_to_rss_function);
     env
 }
 
+// The `<script>` of the live reload client in serve mode, empty otherwise.
+fn live_reload_script_function() -> Value {
+    if crate::current_mode() == crate::Mode::Serve {
+        Value::from_safe_string(format!(
+            r#"<script src="{}"></script>"#,
+            crate::cmd::LIVE_RELOAD_SCRIPT_PATH
+        ))
+    } else {
+        Value::from("")
+    }
+}
+
 fn now_function() -> String {
-    time::OffsetDateTime::now_utc()
-        .format(&time::format_description::well_known::Rfc3339)
+    OffsetDateTime::now_utc()
+        .format(&format_description::well_known::Rfc3339)
         .expect("Failed to format now time.")
 }
 
+/// The now time with the configured offset, see `Genkit::utc_offset`.
+///
+/// The `OffsetDateTime::now_local` always fails in the multithreaded process,
+/// so the offset is configured rather than detected.
+pub(crate) fn now_local() -> OffsetDateTime {
+    let offset = UTC_OFFSET.read().unwrap_or(UtcOffset::UTC);
+    OffsetDateTime::now_utc().to_offset(offset)
+}
+
+// The now time with the configured offset, formatted in RFC3339 or the
+// `format` description of the `time` crate, such as `[hour]:[minute]`.
+fn now_local_function(format: Option<&str>) -> Result<String, Error> {
+    format_time(now_local(), format)
+}
+
+fn format_time(time: OffsetDateTime, format: Option<&str>) -> Result<String, Error> {
+    let result = match format {
+        Some(format) => format_description::parse(format)
+            .map_err(|err| err.to_string())
+            .and_then(|format| time.format(&format).map_err(|err| err.to_string())),
+        None => time
+            .format(&format_description::well_known::Rfc3339)
+            .map_err(|err| err.to_string()),
+    };
+    result.map_err(|err| Error::new(ErrorKind::InvalidOperation, err))
+}
+
 fn trim_start_matches_filter(s: &str, prefix: &str) -> String {
     s.trim_start_matches(prefix).to_string()
 }
 
-fn markdown_to_rss_function(markdown: &str) -> String {
-    let (html, _) = _render_html(markdown, false, true);
-    html
+// Get the value of the dotted field `path`, such as `meta.date`.
+// Return `None` if the field is missing or none.
+fn get_field(value: &Value, path: &str) -> Option<Value> {
+    path.split('.').try_fold(value.clone(), |value, key| {
+        value
+            .get_attr(key)
+            .ok()
+            .filter(|value| !value.is_undefined() && !value.is_none())
+    })
+}
+
+// Group the objects by the field `path` in the order of first appearance,
+// return a list of `{ key, items }`. The objects missing the field are
+// grouped into the last group whose key is none.
+fn group_by_filter(values: Vec<Value>, path: &str) -> Vec<Value> {
+    let mut groups: Vec<(Value, Vec<Value>)> = Vec::new();
+    let mut missing = Vec::new();
+    for value in values {
+        match get_field(&value, path) {
+            Some(key) => match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, items)) => items.push(value),
+                None => groups.push((key, vec![value])),
+            },
+            None => missing.push(value),
+        }
+    }
+    if !missing.is_empty() {
+        groups.push((Value::from(()), missing));
+    }
+    groups
+        .into_iter()
+        .map(|(key, items)| context! { key => key, items => items })
+        .collect()
+}
+
+// Sort the objects by the field `path`, the objects missing
+// the field are always sorted last.
+fn sort_by_filter(values: Vec<Value>, path: &str, reverse: Option<bool>) -> Vec<Value> {
+    let reverse = reverse.unwrap_or(false);
+    let mut values = values
+        .into_iter()
+        .map(|value| (get_field(&value, path), value))
+        .collect::<Vec<_>>();
+    values.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Some(a), Some(b)) if reverse => b.cmp(a),
+        (Some(a), Some(b)) => a.cmp(b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+    values.into_iter().map(|(_, value)| value).collect()
+}
+
+fn markdown_to_html_function(markdown: &str) -> Result<String, Error> {
+    markdown::try_render_html(markdown).map_err(render_error)
+}
+
+fn markdown_to_rss_function(markdown: &str) -> Result<String, Error> {
+    let (html, _) = _render_html(markdown, false, true).map_err(render_error)?;
+    Ok(html)
+}
+
+// Convert the markdown render error to the template error, keep the causes.
+fn render_error(err: anyhow::Error) -> Error {
+    Error::new(ErrorKind::InvalidOperation, format!("{err:#}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{Date, Month, UtcOffset};
+
+    use super::{format_time, init_environment};
+    use crate::{tests::MODE_LOCK, with_mode, Mode};
+
+    #[test]
+    fn test_live_reload_script() {
+        let _lock = MODE_LOCK.lock();
+        let env = init_environment();
+        let render = || env.render_str("{{ live_reload_script() }}", ()).unwrap();
+        assert_eq!(
+            with_mode(Mode::Serve, render),
+            r#"<script src="/__genkit/live_reload.js"></script>"#
+        );
+        assert_eq!(with_mode(Mode::Build, render), "");
+    }
+
+    #[test]
+    fn test_group_by_and_sort_by() {
+        let env = init_environment();
+        let posts = minijinja::context! {
+            posts => vec![
+                minijinja::context! { title => "b", meta => minijinja::context! { year => 2023 } },
+                minijinja::context! { title => "draft" },
+                minijinja::context! { title => "a", meta => minijinja::context! { year => 2024 } },
+                minijinja::context! { title => "c", meta => minijinja::context! { year => 2023 } },
+            ]
+        };
+
+        let template = "{% for g in posts | group_by('meta.year') %}\
+            {{ g.key }}:{% for p in g.items %}{{ p.title }}{% endfor %};{% endfor %}";
+        assert_eq!(
+            env.render_str(template, &posts).unwrap(),
+            "2023:bc;2024:a;none:draft;"
+        );
+
+        let template = "{% for p in posts | sort_by('meta.year') %}{{ p.title }}{% endfor %}";
+        assert_eq!(env.render_str(template, &posts).unwrap(), "bcadraft");
+        let template = "{% for p in posts | sort_by('meta.year', true) %}{{ p.title }}{% endfor %}";
+        assert_eq!(env.render_str(template, &posts).unwrap(), "abcdraft");
+    }
+
+    #[test]
+    fn test_format_time() {
+        let time = Date::from_calendar_date(2024, Month::January, 1)
+            .unwrap()
+            .with_hms(10, 30, 0)
+            .unwrap()
+            .assume_utc()
+            .to_offset(UtcOffset::from_hms(8, 0, 0).unwrap());
+        assert_eq!(
+            format_time(time, None).unwrap(),
+            "2024-01-01T18:30:00+08:00"
+        );
+        assert_eq!(
+            format_time(time, Some("[year]-[month]-[day] [hour]:[minute]")).unwrap(),
+            "2024-01-01 18:30"
+        );
+        assert!(format_time(time, Some("[invalid]")).is_err());
+    }
 }
@@ -1,19 +1,30 @@
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use clap::Command;
+use config::GenkitConfig;
 use entity::MarkdownConfig;
 use parking_lot::RwLock;
 
 mod cmd;
 mod code_blocks;
+mod config;
+pub mod content;
 pub mod context;
 mod data;
+mod embed;
 mod engine;
 pub mod entity;
 pub mod helpers;
 pub mod html;
+mod image;
 pub mod jinja;
 pub mod markdown;
+pub mod output;
+pub mod pagination;
+mod profile;
 
 pub use clap::ArgMatches;
 pub use cmd::Cmd;
@@ -22,12 +33,13 @@ pub use context::Context;
 pub use entity::Entity;
 pub use markdown::MarkdownVisitor;
 pub use minijinja::Environment;
+pub use output::GeneratedPage;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 
 static MODE: RwLock<Mode> = parking_lot::const_rwlock(Mode::Unknown);
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Mode {
     Build,
     Serve,
@@ -39,10 +51,34 @@ pub fn current_mode() -> Mode {
     *MODE.read()
 }
 
-fn set_current_mode(mode: Mode) {
+/// Set current run mode, which is set by [`Genkit::run`] automatically.
+///
+/// Prefer [`with_mode`] in tests to avoid leaking the mode to other tests.
+pub fn set_mode(mode: Mode) {
     *MODE.write() = mode;
 }
 
+/// Call `f` with the run mode set to `mode`, the previous mode is
+/// restored after `f` returns, even if `f` panics.
+///
+/// Note the mode is process-global rather than thread-isolated, the code
+/// running concurrently on other threads also observes the `mode`. The tests
+/// which touch the mode should be serialized, such as by a shared mutex.
+pub fn with_mode<T>(mode: Mode, f: impl FnOnce() -> T) -> T {
+    // Restore the previous mode on drop.
+    struct ModeGuard(Mode);
+
+    impl Drop for ModeGuard {
+        fn drop(&mut self) {
+            set_mode(self.0);
+        }
+    }
+
+    let _guard = ModeGuard(current_mode());
+    set_mode(mode);
+    f()
+}
+
 #[allow(unused_variables)]
 pub trait Generator {
     type Entity: Entity;
@@ -74,6 +110,14 @@ pub trait Generator {
     fn get_markdown_config(&self, entity: &Self::Entity) -> Option<MarkdownConfig> {
         None
     }
+
+    /// Called after all entities rendered, `pages` are the pages generated
+    /// by [`output::render_to_file`] or [`output::write_page`] in this build.
+    ///
+    /// This is the place to build the feed or sitemap from the generated pages.
+    fn on_finish(&self, pages: &[GeneratedPage], dest: &Path) -> Result<()> {
+        Ok(())
+    }
 }
 
 pub struct Genkit<G> {
@@ -81,6 +125,8 @@ pub struct Genkit<G> {
     command_map: HashMap<String, Box<dyn Cmd>>,
     generator: G,
     banner: Option<&'static str>,
+    header_rules: Vec<cmd::HeaderRule>,
+    live_reload_path: &'static str,
 }
 
 impl<G> Genkit<G>
@@ -97,6 +143,8 @@ where
             command_map: HashMap::new(),
             generator,
             banner: None,
+            header_rules: Vec::new(),
+            live_reload_path: cmd::DEFAULT_LIVE_RELOAD_PATH,
         }
     }
 
@@ -113,6 +161,138 @@ where
         self
     }
 
+    /// Download the url preview images into the `dir` of the dest directory
+    /// under the content-hashed names, and use the local image in the previews.
+    /// The preview is fetched again if its cached image is missing in the dest.
+    ///
+    /// Only the images smaller than 5MB are cached, SVG images are rejected,
+    /// the remote image url is kept if failed to download.
+    pub fn preview_image_dir(self, dir: &'static str) -> Self {
+        data::set_preview_image_dir(dir);
+        self
+    }
+
+    /// Register the custom `parser` to convert the files of the `extension`
+    /// (such as `org` or `adoc`) to HTML, the arguments of the parser are
+    /// the file content and path.
+    ///
+    /// The generator renders the content files by [`content::render_file`],
+    /// which falls back to markdown for the unregistered extensions.
+    pub fn content_parser<F>(self, extension: &str, parser: F) -> Self
+    where
+        F: Fn(&str, &Path) -> Result<String> + Send + Sync + 'static,
+    {
+        content::register_parser(extension, std::sync::Arc::new(parser));
+        self
+    }
+
+    /// Delete the data file (`genkit.json` by default) if it becomes empty,
+    /// such as all previewed links are removed. Otherwise the file is kept
+    /// with the empty data.
+    pub fn prune_empty_data(self, prune_empty: bool) -> Self {
+        data::set_prune_empty(prune_empty);
+        self
+    }
+
+    /// Embed the templates into the binary, they are registered into
+    /// the [`Environment`] before [`Generator::on_extend_environment`],
+    /// so the templates on disk with the same name still override them.
+    pub fn embedded_templates<I>(self, templates: I) -> Self
+    where
+        I: IntoIterator<Item = (&'static str, &'static str)>,
+    {
+        embed::add_templates(templates);
+        self
+    }
+
+    /// Embed the static assets into the binary, keyed by the path relative
+    /// to the dest directory. Each build writes the embedded assets into
+    /// the dest directory unless the file exists, and the serve command
+    /// serves the embedded asset if no file exists in the served directory.
+    pub fn embedded_assets<I>(self, assets: I) -> Self
+    where
+        I: IntoIterator<Item = (&'static str, &'static [u8])>,
+    {
+        embed::add_assets(assets);
+        self
+    }
+
+    /// Set a site-global value, such as the site name or git commit. The value
+    /// is injected into every [`Context`] the engine creates and also registered
+    /// as a global of the [`Environment`], so every template can see it.
+    ///
+    /// The value set by the entity wins on key conflict.
+    pub fn global_context<T>(self, key: impl Into<String>, value: &T) -> Self
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        context::insert_global(key.into(), value);
+        self
+    }
+
+    /// Exclude the source paths matching the `glob` from the output, such as
+    /// `README.md` or `_drafts/**`.
+    ///
+    /// The entities whose [`Entity::source_path`] is excluded are never parsed
+    /// or rendered, the generator should also skip the excluded paths when
+    /// enumerating the sources in [`Generator::on_load`], see [`helpers::is_excluded`].
+    /// The dest directory inside the source directory is always excluded.
+    pub fn exclude(self, glob: impl Into<String>) -> Self {
+        helpers::add_exclude_glob(glob.into());
+        self
+    }
+
+    /// Exclude the source paths with any name starting with `_` from the output,
+    /// such as `_partials/**` and `_drafts/**`, default to false.
+    pub fn exclude_underscored(self, exclude: bool) -> Self {
+        helpers::set_exclude_underscored(exclude);
+        self
+    }
+
+    /// Set the UTC offset applied to the `now_local` template function and
+    /// the rebuild time printed in the watch mode, default to UTC.
+    /// The `now` function always returns the UTC time.
+    pub fn utc_offset(self, offset: time::UtcOffset) -> Self {
+        jinja::set_utc_offset(offset);
+        self
+    }
+
+    /// Set the response headers on the paths matching the `glob` in the serve
+    /// command, such as CSP, CORS or cache rules, to mirror the production
+    /// header configs. The `glob` is matched against the request path, see
+    /// [`helpers::glob_match`], e.g. `/assets/**`.
+    ///
+    /// All matching rules are applied in the order added, the later rule
+    /// overrides the same header of the earlier ones.
+    pub fn serve_headers<K, V>(mut self, glob: impl Into<String>, headers: Vec<(K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.header_rules.push(cmd::HeaderRule {
+            glob: glob.into(),
+            headers: headers
+                .into_iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect(),
+        });
+        self
+    }
+
+    /// Set the websocket path of the live reload in the serve command,
+    /// default to `/live_reload`. Change it if it collides with your route.
+    ///
+    /// Put `{{ live_reload_script() }}` into the template to include the
+    /// client, which reconnects after the server restarts and reloads the
+    /// page once the new build is ready. It renders nothing in build mode.
+    ///
+    /// The path must start with `/` and not be `/`, otherwise the serve
+    /// command fails.
+    pub fn live_reload_path(mut self, path: &'static str) -> Self {
+        self.live_reload_path = path;
+        self
+    }
+
     pub fn banner(mut self, banner: &'static str) -> Self {
         self.banner = Some(banner);
         self
@@ -127,36 +307,78 @@ where
     }
 
     pub async fn run(mut self) -> Result<()> {
-        self = self.add_command(cmd::LintCmd);
+        self = self
+            .add_command(cmd::LintCmd)
+            .add_command(cmd::LinksCmd)
+            .add_command(cmd::RenderCmd);
 
         let name = self.root_command.get_name().to_owned();
         let matches = self.root_command.arg_required_else_help(true).get_matches();
+        let config = GenkitConfig::load(matches.get_one::<String>("config").map(String::as_str))?;
         match matches.subcommand() {
             Some(("build", arg_matches)) => {
-                set_current_mode(Mode::Build);
+                set_mode(Mode::Build);
+                set_markdown_config_override(arg_matches, &config)?;
                 let source = arg_matches
                     .get_one::<String>("source")
+                    .or(config.source.as_ref())
                     .cloned()
                     .unwrap_or_else(|| ".".into());
                 let dest = arg_matches
                     .get_one::<String>("dest")
+                    .or(config.dest.as_ref())
                     .cloned()
                     .unwrap_or_else(|| "build".into());
                 let watch = arg_matches.get_flag("watch");
+                entity::set_only_filter(arg_matches.get_one::<String>("only").cloned());
+                if arg_matches.get_flag("time") {
+                    profile::enable();
+                }
+
+                let post_build =
+                    config
+                        .post_build_command
+                        .clone()
+                        .map(|command| cmd::PostBuildCommand {
+                            command,
+                            on_watch: config.post_build_on_watch,
+                        });
 
-                cmd::watch_build(self.generator, &source, &dest, watch, None).await?;
+                cmd::watch_build(self.generator, &source, &dest, watch, None, post_build).await?;
                 println!("Build success! The build directory is `{dest}`.");
             }
             Some(("serve", arg_matches)) => {
-                set_current_mode(Mode::Serve);
+                set_mode(Mode::Serve);
+                set_markdown_config_override(arg_matches, &config)?;
                 let source = arg_matches
                     .get_one::<String>("source")
+                    .or(config.source.as_ref())
                     .cloned()
                     .unwrap_or_else(|| ".".into());
-                let port = arg_matches.get_one::<u16>("port").copied().unwrap_or(3000);
+                let port = arg_matches
+                    .get_one::<u16>("port")
+                    .or(config.port.as_ref())
+                    .copied()
+                    .unwrap_or(3000);
                 let open = arg_matches.get_flag("open");
+                let precompressed = arg_matches.get_flag("precompressed");
+
+                let prebuilt = arg_matches.get_one::<String>("no-build").map(PathBuf::from);
+                let dest = arg_matches.get_one::<String>("dest").map(PathBuf::from);
 
-                cmd::run_serve(self.generator, &source, port, open, &name, self.banner).await?;
+                let options = cmd::ServeOptions {
+                    source: &source,
+                    port,
+                    open_browser: open,
+                    precompressed,
+                    prebuilt,
+                    dest,
+                    name: &name,
+                    banner: self.banner,
+                    header_rules: &self.header_rules,
+                    live_reload_path: self.live_reload_path,
+                };
+                cmd::run_serve(self.generator, options).await?;
             }
             Some((name, arg_matches)) => {
                 if let Some(command) = self.command_map.get(name) {
@@ -168,3 +390,64 @@ where
         Ok(())
     }
 }
+
+// Override the markdown config with the command line args,
+// fallback to the config file if the arg is absent.
+fn set_markdown_config_override(arg_matches: &ArgMatches, config: &GenkitConfig) -> Result<()> {
+    data::set_markdown_config_override(markdown_config_override(arg_matches, config)?);
+    Ok(())
+}
+
+// The markdown config fields overridden by the command line args and the config file,
+// the args have higher priority. Fail if the highlight theme doesn't exist.
+pub(crate) fn markdown_config_override(
+    arg_matches: &ArgMatches,
+    config: &GenkitConfig,
+) -> Result<data::MarkdownConfigOverride> {
+    let highlight_theme = arg_matches
+        .get_one::<String>("highlight-theme")
+        .or(config.highlight_theme.as_ref())
+        .cloned();
+    if let Some(theme) = highlight_theme.as_ref() {
+        if let Err(themes) = markdown::check_highlight_theme(theme) {
+            bail!(
+                "No highlight theme `{theme}` found, available themes: {}",
+                themes.join(", ")
+            );
+        }
+    }
+
+    Ok(data::MarkdownConfigOverride {
+        highlight_code: arg_matches
+            .get_flag("no-highlight")
+            .then_some(false)
+            .or(config.highlight_code),
+        highlight_theme,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use parking_lot::Mutex;
+
+    use super::{current_mode, with_mode, Mode};
+
+    // Serialize the tests which set or read the global mode.
+    pub(crate) static MODE_LOCK: Mutex<()> = parking_lot::const_mutex(());
+
+    #[test]
+    fn test_with_mode() {
+        let _lock = MODE_LOCK.lock();
+        let previous = current_mode();
+        let mode = with_mode(Mode::Serve, || {
+            assert_eq!(with_mode(Mode::Build, current_mode), Mode::Build);
+            current_mode()
+        });
+        assert_eq!(mode, Mode::Serve);
+        assert_eq!(current_mode(), previous);
+
+        let result = std::panic::catch_unwind(|| with_mode(Mode::Build, || panic!("failed")));
+        assert!(result.is_err());
+        assert_eq!(current_mode(), previous);
+    }
+}
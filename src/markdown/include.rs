@@ -0,0 +1,122 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+/// Expand the `{{ include "path" }}` directives of the markdown.
+///
+/// The directive must occupy a whole line, directives inside the fenced
+/// code blocks are kept as is. The path is relative to the directory of the
+/// `current` markdown file and must not escape the `root` directory.
+/// Included files are expanded recursively, a cyclic include is an error.
+pub fn expand_includes(markdown: &str, current: &Path, root: &Path) -> Result<String> {
+    let root = fs::canonicalize(root)?;
+    let current = fs::canonicalize(current)?;
+    let mut stack = vec![current.clone()];
+    expand(markdown, &current, &root, &mut stack)
+}
+
+fn expand(markdown: &str, current: &Path, root: &Path, stack: &mut Vec<PathBuf>) -> Result<String> {
+    let mut output = String::with_capacity(markdown.len());
+    let mut in_fenced_block = false;
+    for line in markdown.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fenced_block = !in_fenced_block;
+        }
+
+        match parse_directive(trimmed) {
+            Some(path) if !in_fenced_block => {
+                let base = current.parent().unwrap_or(root);
+                let file = fs::canonicalize(base.join(path))
+                    .with_context(|| format!("Included file `{path}` not found"))?;
+                if !file.starts_with(root) {
+                    bail!("Included file `{path}` is outside of the source directory");
+                }
+                if stack.contains(&file) {
+                    bail!("Cyclic include of `{}`", file.display());
+                }
+
+                let content = fs::read_to_string(&file)?;
+                stack.push(file.clone());
+                let expanded = expand(&content, &file, root, stack)?;
+                output.push_str(expanded.trim_end_matches('\n'));
+                stack.pop();
+            }
+            _ => output.push_str(line),
+        }
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+// Parse the path of `{{ include "path" }}` directive.
+fn parse_directive(line: &str) -> Option<&str> {
+    let inner = line.strip_prefix("{{")?.strip_suffix("}}")?.trim();
+    let path = inner.strip_prefix("include")?.trim();
+    path.strip_prefix('"')?.strip_suffix('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    fn setup(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("genkit-include-{name}"));
+        for (path, content) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_parse_directive() {
+        assert_eq!(parse_directive(r#"{{ include "a.md" }}"#), Some("a.md"));
+        assert_eq!(parse_directive(r#"{{include "a/b.md"}}"#), Some("a/b.md"));
+        assert_eq!(parse_directive(r#"{{ include a.md }}"#), None);
+        assert_eq!(parse_directive(r#"{{ title }}"#), None);
+    }
+
+    #[test]
+    fn test_expand_includes() {
+        let root = setup(
+            "basic",
+            &[
+                ("post.md", "# Post\n{{ include \"partials/intro.md\" }}\n"),
+                ("partials/intro.md", "Intro\n{{ include \"outro.md\" }}"),
+                ("partials/outro.md", "Outro"),
+            ],
+        );
+        let markdown = fs::read_to_string(root.join("post.md")).unwrap();
+        let expanded = expand_includes(&markdown, &root.join("post.md"), &root).unwrap();
+        assert_eq!(expanded, "# Post\nIntro\nOutro\n");
+
+        let markdown = "```\n{{ include \"partials/intro.md\" }}\n```";
+        let expanded = expand_includes(markdown, &root.join("post.md"), &root).unwrap();
+        assert_eq!(expanded, format!("{markdown}\n"));
+    }
+
+    #[test]
+    fn test_expand_includes_rejected() {
+        let root = setup(
+            "cycle",
+            &[
+                ("site/a.md", "{{ include \"b.md\" }}"),
+                ("site/b.md", "{{ include \"a.md\" }}"),
+                ("secret.md", "secret"),
+            ],
+        );
+        let site = root.join("site");
+        let markdown = fs::read_to_string(site.join("a.md")).unwrap();
+        assert!(expand_includes(&markdown, &site.join("a.md"), &site).is_err());
+
+        let markdown = "{{ include \"../secret.md\" }}";
+        assert!(expand_includes(markdown, &site.join("a.md"), &site).is_err());
+    }
+}
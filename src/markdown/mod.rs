@@ -1,20 +1,63 @@
 use pulldown_cmark::Event::{Code, End, HardBreak, Rule, SoftBreak, Start, Text};
 use pulldown_cmark::{Options, Parser, Tag, TagEnd};
+use pulldown_cmark_escape::{escape_href, escape_html};
 
+mod include;
 mod render;
 mod visitor;
 
+use std::{
+    cell::RefCell,
+    fs,
+    path::{Path, PathBuf},
+};
+
 use crate::data;
+use anyhow::Result;
 
+pub use include::expand_includes;
+pub use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+pub(crate) use render::check_highlight_theme;
 pub use render::MarkdownRender;
-pub use render::Toc;
+pub use render::{Toc, TocNode};
 pub use visitor::MarkdownVisitor;
 
+thread_local! {
+    // The source file currently rendering in this thread.
+    static CURRENT_SOURCE_FILE: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with the `source` file as the current rendering source file.
+///
+/// The urls previewed during `f` record the `source` as their provenance,
+/// which is used by the `lint --since` command to lint the changed files only.
+/// The sources previously recorded from the `source` file are cleared first.
+pub fn with_source_file<T, F: FnOnce() -> T>(source: &Path, f: F) -> T {
+    // Restore the previous source file on drop, even if `f` panics.
+    struct SourceFileGuard(Option<PathBuf>);
+
+    impl Drop for SourceFileGuard {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CURRENT_SOURCE_FILE.with(|s| *s.borrow_mut() = previous);
+        }
+    }
+
+    data::clear_url_sources(source);
+    let _guard =
+        SourceFileGuard(CURRENT_SOURCE_FILE.with(|s| s.replace(Some(source.to_path_buf()))));
+    f()
+}
+
+pub(crate) fn current_source_file() -> Option<PathBuf> {
+    CURRENT_SOURCE_FILE.with(|s| s.borrow().clone())
+}
+
 pub(crate) fn _render_html(
     markdown: &str,
     enable_toc: bool,
     enable_rss_mode: bool,
-) -> (String, Vec<Toc>) {
+) -> Result<(String, Vec<Toc>)> {
     let guard = data::read();
     let markdown_config = guard.get_markdown_config();
     let mut mr = MarkdownRender::new(markdown_config);
@@ -27,17 +70,36 @@ pub(crate) fn _render_html(
     if let Some(visitor) = data::get_markdown_visitor() {
         mr.set_markdown_visitor(visitor);
     }
-    let html = mr.render_html(markdown);
-    (html, mr.get_toc())
+    let html = mr.try_render_html(markdown)?;
+    Ok((html, mr.get_toc()))
 }
 
+/// Render the markdown to HTML.
+///
+/// # Panics
+///
+/// Panics if any template fails to render, see [`try_render_html`].
 pub fn render_html(markdown: &str) -> String {
-    let (html, _) = _render_html(markdown, false, false);
-    html
+    try_render_html(markdown).unwrap_or_else(|err| panic!("{err:?}"))
+}
+
+/// Render the markdown to HTML, return the error of the failed template
+/// with the template name and the source file currently rendering.
+pub fn try_render_html(markdown: &str) -> Result<String> {
+    let (html, _) = _render_html(markdown, false, false)?;
+    Ok(html)
 }
 
 pub fn render_html_with_toc(markdown: &str) -> (String, Vec<Toc>) {
-    _render_html(markdown, true, false)
+    _render_html(markdown, true, false).unwrap_or_else(|err| panic!("{err:?}"))
+}
+
+/// Render the markdown file to HTML, the `{{ include "path" }}` directives
+/// are expanded before rendering. See [`expand_includes`].
+pub fn render_html_file(path: &Path, root: &Path) -> Result<String> {
+    let markdown = fs::read_to_string(path)?;
+    let markdown = expand_includes(&markdown, path, root)?;
+    with_source_file(path, || try_render_html(&markdown))
 }
 
 /// Extract the description from markdown content.
@@ -84,6 +146,107 @@ pub fn extract_description(markdown: &str) -> String {
         .unwrap_or_default()
 }
 
+/// Extract the rich excerpt from markdown content.
+///
+/// Unlike [`extract_description`], the excerpt keeps the inline formatting
+/// whose tag is in the `allowed_tags`, such as `em`, `strong`, `code`, `del`
+/// and `a`. The excerpt contains at most `max_chars` visible chars, all
+/// open tags are closed if it has been truncated.
+///
+/// Headings, images and code blocks are ignored.
+pub fn extract_rich_excerpt(markdown: &str, max_chars: usize, allowed_tags: &[&str]) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+
+    let mut html = String::new();
+    let mut chars_count = 0;
+    // The open inline tags, `None` if the tag isn't allowed.
+    let mut open_tags: Vec<Option<&str>> = Vec::new();
+    // The depth of the ignored tags we are in.
+    let mut ignored_depth = 0;
+
+    let is_allowed = |tag: &str| allowed_tags.contains(&tag);
+    for event in Parser::new_ext(markdown, options) {
+        match event {
+            Start(Tag::Heading { .. } | Tag::Image { .. } | Tag::CodeBlock(_)) => {
+                ignored_depth += 1;
+            }
+            End(TagEnd::Heading(_) | TagEnd::Image | TagEnd::CodeBlock) => {
+                ignored_depth -= 1;
+            }
+            _ if ignored_depth > 0 => {}
+            Start(tag) => {
+                let name = match &tag {
+                    Tag::Emphasis => "em",
+                    Tag::Strong => "strong",
+                    Tag::Strikethrough => "del",
+                    Tag::Link { .. } => "a",
+                    Tag::Paragraph | Tag::Item => {
+                        // Separate the blocks with a blank.
+                        if chars_count > 0 && !html.ends_with(' ') {
+                            html.push(' ');
+                            chars_count += 1;
+                        }
+                        continue;
+                    }
+                    _ => continue,
+                };
+                if is_allowed(name) {
+                    html.push('<');
+                    html.push_str(name);
+                    if let Tag::Link { dest_url, .. } = &tag {
+                        html.push_str(" href=\"");
+                        escape_href(&mut html, dest_url).unwrap();
+                        html.push('"');
+                    }
+                    html.push('>');
+                    open_tags.push(Some(name));
+                } else {
+                    open_tags.push(None);
+                }
+            }
+            End(TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough | TagEnd::Link) => {
+                if let Some(Some(name)) = open_tags.pop() {
+                    html.push_str(&format!("</{name}>"));
+                }
+            }
+            Text(text) | Code(text) => {
+                let is_code = matches!(event, Code(_));
+                let take = (max_chars - chars_count).min(text.chars().count());
+                let text = text.chars().take(take).collect::<String>();
+                chars_count += take;
+
+                let code_allowed = is_code && is_allowed("code");
+                if code_allowed {
+                    html.push_str("<code>");
+                }
+                escape_html(&mut html, &text).unwrap();
+                if code_allowed {
+                    html.push_str("</code>");
+                }
+                if chars_count >= max_chars {
+                    break;
+                }
+            }
+            SoftBreak | HardBreak => {
+                if chars_count < max_chars {
+                    html.push(' ');
+                    chars_count += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Close all open tags if the excerpt has been truncated.
+    while let Some(tag) = open_tags.pop() {
+        if let Some(name) = tag {
+            html.push_str(&format!("</{name}>"));
+        }
+    }
+    html.trim_end().to_owned()
+}
+
 /// Convert markdown into plain text.
 #[must_use]
 pub fn strip_markdown(markdown: &str) -> String {
@@ -153,6 +316,17 @@ mod tests {
     use super::*;
     use test_case::test_case;
 
+    #[test]
+    fn test_with_source_file() {
+        let (a, b) = (Path::new("a.md"), Path::new("b.md"));
+        with_source_file(a, || {
+            let result = std::panic::catch_unwind(|| with_source_file(b, || panic!("failed")));
+            assert!(result.is_err());
+            assert_eq!(current_source_file().as_deref(), Some(a));
+        });
+        assert_eq!(current_source_file(), None);
+    }
+
     #[test_case("# `Rust` is **Awesome**!")]
     fn test_word_count(markdown: &str) {
         assert_eq!(3, count_words(markdown))
@@ -201,6 +375,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_extract_rich_excerpt() {
+        let markdown = "Hello *Rust* and **`genkit`** [link](https://example.com)!";
+        assert_eq!(
+            extract_rich_excerpt(markdown, 200, &["em", "code", "a"]),
+            r#"Hello <em>Rust</em> and <code>genkit</code> <a href="https://example.com">link</a>!"#
+        );
+        assert_eq!(
+            extract_rich_excerpt(markdown, 200, &[]),
+            "Hello Rust and genkit link!"
+        );
+    }
+
+    #[test]
+    fn test_extract_rich_excerpt_truncated() {
+        let markdown = "# Title\n\nHello **bold _nested_ text** <tail>";
+        assert_eq!(
+            extract_rich_excerpt(markdown, 14, &["strong", "em"]),
+            "Hello <strong>bold <em>nes</em></strong>"
+        );
+        assert_eq!(
+            extract_rich_excerpt("a < b\n\nc & d", 200, &[]),
+            "a &lt; b c &amp; d"
+        );
+    }
+
     #[test]
     fn basic_inline_strong() {
         let markdown = r#"**Hello**"#;
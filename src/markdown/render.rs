@@ -1,15 +1,23 @@
-use std::{collections::BTreeSet, mem};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashSet},
+    mem,
+    ops::Range,
+};
 
 use crate::{
     code_blocks::{self, url_preview, CalloutBlock, CodeBlock, Fenced, QuoteBlock},
+    data,
     entity::MarkdownConfig,
-    jinja::init_environment,
+    image, jinja, markdown,
 };
 
+use anyhow::Result;
 use minijinja::{context, Environment};
 use once_cell::sync::Lazy;
 use pulldown_cmark::TagEnd;
 use pulldown_cmark::*;
+use pulldown_cmark_escape::{escape_href, escape_html};
 use serde::Serialize;
 use syntect::{
     dumps::from_binary, highlighting::ThemeSet, html::highlighted_html_for_string,
@@ -28,6 +36,24 @@ static THEME_SET: Lazy<ThemeSet> = Lazy::new(|| {
     theme_set
 });
 
+/// Check whether the highlight theme exists, return all available
+/// theme names as the error if not.
+///
+/// The `THEME_SET` is loaded lazily on the first check.
+pub(crate) fn check_highlight_theme(theme: &str) -> Result<(), Vec<&'static str>> {
+    if THEME_SET.themes.contains_key(theme) {
+        Ok(())
+    } else {
+        let mut themes = THEME_SET
+            .themes
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        themes.sort();
+        Err(themes)
+    }
+}
+
 // Render mode.
 enum RenderMode {
     // RSS mode.
@@ -41,19 +67,32 @@ enum RenderMode {
 /// Markdown html render.
 pub struct MarkdownRender<'a> {
     markdown_env: Environment<'a>,
+    // The markdown currently rendering.
+    markdown: &'a str,
+    // The offset of the event currently visiting.
+    offset: usize,
     markdown_config: &'a MarkdownConfig,
     visitor: Option<Box<dyn MarkdownVisitor + Send + Sync>>,
     code_block_fenced: Option<CowStr<'a>>,
-    // Whether we are processing image parsing
-    processing_image: bool,
-    // The alt of the processing image
-    image_alt: Option<CowStr<'a>>,
+    // The url, title and alt of the image currently being processed.
+    // The alt is collected from the text until the end of the image.
+    image: Option<(CowStr<'a>, CowStr<'a>, String)>,
+    // Whether we are in a code block, fenced or indented.
+    in_code_block: bool,
+    // The depth of the links we are in.
+    link_depth: usize,
     // The heading currently being processed.
     curr_heading: Option<Heading<'a>>,
     levels: BTreeSet<usize>,
     render_mode: RenderMode,
     // All headings from markdown, aka, Table of content.
     headings: Option<Vec<Heading<'a>>>,
+    // The first template error during rendering.
+    error: Option<anyhow::Error>,
+    // The abbreviation terms, the longer first to match the longest term.
+    abbreviation_terms: Vec<&'a str>,
+    // The abbreviation terms already wrapped in this page.
+    abbreviated: HashSet<&'a str>,
 }
 
 #[derive(Debug, Serialize)]
@@ -66,11 +105,49 @@ pub struct Toc {
     // Here is the syntax:
     // `# Long title {#title}` parse the id: title
     // See https://docs.rs/pulldown-cmark/latest/pulldown_cmark/struct.Options.html#associatedconstant.ENABLE_HEADING_ATTRIBUTES
-    id: Option<String>,
+    // Or generated from the title if the level is in `MarkdownConfig::anchor_levels`,
+    // empty means the heading isn't anchorable.
+    id: String,
     // Heading title
     title: String,
 }
 
+/// The node of the nested Table of Content tree.
+#[derive(Debug, Serialize)]
+pub struct TocNode {
+    pub toc: Toc,
+    pub children: Vec<TocNode>,
+}
+
+// Build the nested tree from the flat toc list by the relative depth,
+// the skipped levels (h2 -> h4) are nested as a direct child.
+fn build_toc_tree(tocs: Vec<Toc>) -> Vec<TocNode> {
+    fn attach(stack: &mut [TocNode], roots: &mut Vec<TocNode>, node: TocNode) {
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => roots.push(node),
+        }
+    }
+
+    let mut roots = Vec::new();
+    // The ancestors of the current node, from the outermost.
+    let mut stack: Vec<TocNode> = Vec::new();
+    for toc in tocs {
+        while stack.last().is_some_and(|node| node.toc.depth >= toc.depth) {
+            let node = stack.pop().unwrap();
+            attach(&mut stack, &mut roots, node);
+        }
+        stack.push(TocNode {
+            toc,
+            children: Vec::new(),
+        });
+    }
+    while let Some(node) = stack.pop() {
+        attach(&mut stack, &mut roots, node);
+    }
+    roots
+}
+
 /// Markdown heading.
 #[derive(Debug)]
 pub struct Heading<'a> {
@@ -84,7 +161,7 @@ impl<'a> Heading<'a> {
             toc: Toc {
                 depth: level,
                 level,
-                id,
+                id: id.unwrap_or_default(),
                 title: String::new(),
             },
             events: Vec::new(),
@@ -101,47 +178,59 @@ impl<'a> Heading<'a> {
         self
     }
 
-    // Render heading to html.
-    fn render(&mut self, env: &Environment<'a>) -> Event<'static> {
-        if self.toc.id.is_none() {
+    // Render heading to html, generate the id from the title if `auto_id`.
+    fn render(&mut self, env: &Environment<'a>, auto_id: bool) -> Result<Event<'static>> {
+        if self.toc.id.is_empty() && auto_id {
             // Fallback to raw text as the anchor id if the user didn't specify an id.
-            self.toc.id = Some(self.toc.title.to_lowercase());
             // Replace blank char with '-'.
-            if let Some(id) = self.toc.id.as_mut() {
-                *id = id.replace(' ', "-");
-            }
+            self.toc.id = self.toc.title.to_lowercase().replace(' ', "-");
         }
 
         let mut heading = String::new();
         let events = mem::take(&mut self.events);
         html::push_html(&mut heading, events.into_iter());
 
-        let html = env
-            .get_template("__genkit_heading.jinja")
-            .expect("Get heading template failed.")
-            .render(context! {
+        let html = render_template(
+            env,
+            "__genkit_heading.jinja",
+            context! {
                 heading,
                 level => self.toc.level,
                 id => self.toc.id,
-            })
-            .expect("Render heading failed.");
-        Event::Html(html.into())
+            },
+        )?;
+        Ok(Event::Html(html.into()))
     }
 }
 
 impl<'a> MarkdownRender<'a> {
     pub fn new(markdown_config: &'a MarkdownConfig) -> Self {
         MarkdownRender {
-            markdown_env: init_environment(),
+            markdown_env: jinja::markdown_environment(),
+            markdown: "",
+            offset: 0,
             markdown_config,
             visitor: None,
             code_block_fenced: None,
-            processing_image: false,
-            image_alt: None,
+            image: None,
+            in_code_block: false,
+            link_depth: 0,
             curr_heading: None,
             levels: BTreeSet::new(),
             render_mode: RenderMode::Article,
             headings: None,
+            error: None,
+            abbreviation_terms: {
+                let mut terms = markdown_config
+                    .abbreviations
+                    .keys()
+                    .map(String::as_str)
+                    .filter(|term| !term.is_empty())
+                    .collect::<Vec<_>>();
+                terms.sort_by_key(|term| std::cmp::Reverse(term.len()));
+                terms
+            },
+            abbreviated: HashSet::new(),
         }
     }
 
@@ -174,6 +263,11 @@ impl<'a> MarkdownRender<'a> {
         }
     }
 
+    /// Get Table of Content as a nested tree, see [`TocNode`].
+    pub fn get_toc_tree(&mut self) -> Vec<TocNode> {
+        build_toc_tree(self.get_toc())
+    }
+
     // Rebuild the relative depth of toc items.
     fn rebuild_toc_depth(&mut self) {
         if let Some(headings) = self.headings.as_mut() {
@@ -188,28 +282,63 @@ impl<'a> MarkdownRender<'a> {
         }
     }
 
-    fn highlight_syntax(&self, lang: &str, text: &str) -> String {
-        let theme = match THEME_SET.themes.get(&self.markdown_config.highlight_theme) {
+    // Highlight the code with the `theme` of the code block,
+    // fallback to the configured theme if absent or unknown.
+    fn highlight_syntax(&self, lang: &str, text: &str, theme: Option<&str>) -> String {
+        let default_theme = self.markdown_config.highlight_theme.as_str();
+        let theme_name = match theme {
+            Some(theme) if check_highlight_theme(theme).is_ok() => theme,
+            Some(theme) => {
+                println!(
+                    "Warning: no highlight theme `{theme}` found, fallback to `{default_theme}`"
+                );
+                default_theme
+            }
+            None => default_theme,
+        };
+        let theme = match THEME_SET.themes.get(theme_name) {
             Some(theme) => theme,
-            None => panic!(
-                "No theme: `{}` founded",
-                self.markdown_config.highlight_theme
-            ),
+            None => panic!("No theme: `{}` founded", theme_name),
         };
 
-        let syntax = SYNTAX_SET
-            .find_syntax_by_token(lang)
-            // Fallback to plain text if code block not supported
-            .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
+        let syntax = match SYNTAX_SET.find_syntax_by_token(lang) {
+            Some(syntax) => syntax,
+            None => {
+                // Give the visitor a chance to highlight the unknown language.
+                if let Some(html) = self
+                    .visitor
+                    .as_ref()
+                    .and_then(|v| v.visit_highlight(lang, text))
+                {
+                    return html;
+                }
+                // Fallback to plain text if code block not supported
+                SYNTAX_SET.find_syntax_plain_text()
+            }
+        };
         highlighted_html_for_string(text, &SYNTAX_SET, syntax, theme).expect("Highlight failed")
     }
 
     /// Render markdown to HTML.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any template fails to render, see [`MarkdownRender::try_render_html`].
     pub fn render_html(&mut self, markdown: &'a str) -> String {
+        self.try_render_html(markdown)
+            .unwrap_or_else(|err| panic!("{err:?}"))
+    }
+
+    /// Render markdown to HTML, return the first template error
+    /// with the template name and the source file if any.
+    pub fn try_render_html(&mut self, markdown: &'a str) -> Result<String> {
+        self.markdown = markdown;
         let parser_events_iter = Parser::new_ext(markdown, Options::all()).into_offset_iter();
-        let events = parser_events_iter
-            .into_iter()
-            .filter_map(|(event, _)| match event {
+        let parser_events_iter =
+            merge_text_events(markdown, parser_events_iter, self.markdown_config.autolink);
+        let events = parser_events_iter.into_iter().filter_map(|(event, range)| {
+            self.offset = range.start;
+            match event {
                 Event::Start(tag) => self.visit_start_tag(&tag).resolve(|| Event::Start(tag)),
                 Event::End(tag) => self.visit_end_tag(&tag).resolve(|| Event::End(tag)),
                 Event::Code(code) => self.visit_code(&code).resolve(|| Event::Code(code)),
@@ -219,75 +348,110 @@ impl<'a> MarkdownRender<'a> {
                     // We still need record this text event.
                     .resolve(|| Event::Text(text)),
                 _ => Some(event),
-            });
+            }
+        });
+
+        let mut events = events.collect::<Vec<_>>();
+        if let Some(visitor) = self.visitor.as_ref() {
+            events = visitor.transform_events(events);
+        }
 
         let mut html = String::new();
-        html::push_html(&mut html, events);
+        html::push_html(&mut html, events.into_iter());
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
         self.rebuild_toc_depth();
-        html
+        Ok(html)
+    }
+
+    // The line number (1-based) of the event currently visiting.
+    fn current_line(&self) -> usize {
+        self.markdown[..self.offset].matches('\n').count() + 1
     }
 
     /// Render code block. Return rendered HTML string if success,
     ///
     /// If the fenced is unsupported, we simply return `None`.
-    fn render_code_block(&self, fenced: Fenced, block: &'a str) -> Option<String> {
+    fn render_code_block(&self, fenced: Fenced, block: &'a str) -> Result<Option<String>> {
         match fenced.name {
             code_blocks::URL_PREVIEW => {
                 let url = block.trim();
-                url_preview::render(url, fenced.options)
+                Ok(url_preview::render(
+                    url,
+                    fenced.options,
+                    Some(self.current_line()),
+                ))
             }
             code_blocks::CALLOUT => {
-                let html = CalloutBlock::new(fenced.options, block).render().unwrap();
-                Some(html)
+                let html = CalloutBlock::new(&fenced.options, block).render()?;
+                Ok(Some(html))
             }
             code_blocks::QUOTE => {
-                let quote = QuoteBlock::parse(block).unwrap();
-                let html = self
-                    .markdown_env
-                    .get_template("__genkit_quote.jinja")
-                    .expect("Get quote template failed.")
-                    .render(context! {
+                let quote = QuoteBlock::parse(block)?;
+                let html = render_template(
+                    &self.markdown_env,
+                    "__genkit_quote.jinja",
+                    context! {
                         avatar => quote.avatar,
                         author => quote.author,
                         bio => quote.bio,
                         content => quote.content,
-                    })
-                    .expect("Render quote block failed.");
-                Some(html)
+                    },
+                )?;
+                Ok(Some(html))
             }
-            _ => None,
+            _ => Ok(None),
         }
     }
 
     fn visit_start_tag(&mut self, tag: &Tag<'a>) -> Visiting {
+        if self.image.is_some() {
+            // The markups inside the image alt are stripped.
+            return Visiting::Ignore;
+        }
         match tag {
             Tag::CodeBlock(CodeBlockKind::Fenced(name)) => {
+                self.in_code_block = true;
                 self.code_block_fenced = Some(name.clone());
                 Visiting::Ignore
             }
+            Tag::CodeBlock(CodeBlockKind::Indented) => {
+                self.in_code_block = true;
+                Visiting::NotChanged
+            }
             Tag::Image {
                 dest_url, title, ..
             } => {
-                let alt = self.image_alt.take().unwrap_or_else(|| CowStr::from(""));
-                self.processing_image = false;
-
-                self.processing_image = true;
-                // Add loading="lazy" attribute for markdown image.
-                Visiting::Event(Event::Html(
-                    format!(
-                        "<img src=\"{dest_url}\" alt=\"{alt}\" title=\"{title}\" loading=\"lazy\">"
-                    )
-                    .into(),
-                ))
+                // Render the image at the end tag, after the alt text collected.
+                self.image = Some((dest_url.clone(), title.clone(), String::new()));
+                Visiting::Ignore
             }
             Tag::Heading { level, id, .. } => {
-                self.curr_heading = Some(Heading::new(
-                    *level as usize,
-                    id.as_ref().map(|i| i.to_string()),
-                ));
+                let mut level = *level as usize;
+                if level == 1 && self.markdown_config.warn_on_h1 {
+                    match markdown::current_source_file() {
+                        Some(source) => println!(
+                            "Warning: h1 heading found in `{}` at line {}",
+                            source.display(),
+                            self.current_line()
+                        ),
+                        None => {
+                            println!("Warning: h1 heading found at line {}", self.current_line())
+                        }
+                    }
+                }
+                if self.markdown_config.shift_headings {
+                    level = (level + 1).min(6);
+                }
+                self.curr_heading = Some(Heading::new(level, id.as_ref().map(|i| i.to_string())));
                 Visiting::Ignore
             }
             _ => {
+                if let Tag::Link { dest_url, .. } = tag {
+                    self.link_depth += 1;
+                    self.record_outbound_link(dest_url);
+                }
                 if let Some(heading) = self.curr_heading.as_mut() {
                     heading.push_event(Event::Start(tag.to_owned()));
                     Visiting::Ignore
@@ -298,13 +462,95 @@ impl<'a> MarkdownRender<'a> {
         }
     }
 
+    // Record the outbound link with the current source file as its provenance.
+    fn record_outbound_link(&self, url: &str) {
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return;
+        }
+        if let Some(source) = markdown::current_source_file() {
+            let data = data::read();
+            data.mark_rendered(url);
+            data.record_url_source(url, &source, Some(self.current_line()));
+        }
+    }
+
+    // Transform the image url with the configured base url and visitor.
+    fn transform_image_url(&self, url: &str) -> String {
+        let external = is_external_url(url);
+        if external && !self.markdown_config.transform_external_images {
+            return url.to_owned();
+        }
+
+        let url = match self.markdown_config.image_base_url.as_ref() {
+            // Never prepend base url to the external url.
+            Some(base) if !external => format!(
+                "{}/{}",
+                base.trim_end_matches('/'),
+                url.trim_start_matches('/')
+            ),
+            _ => url.to_owned(),
+        };
+        self.visitor
+            .as_ref()
+            .and_then(|v| v.visit_image_url(&url))
+            .unwrap_or(url)
+    }
+
+    // Render the markdown image, the attributes are escaped.
+    fn render_image(&self, dest_url: &str, title: &str, alt: &str) -> String {
+        let mut escaped_alt = String::new();
+        escape_html(&mut escaped_alt, alt).unwrap();
+        let mut escaped_title = String::new();
+        escape_html(&mut escaped_title, title).unwrap();
+        // The performance attributes, only emitted if configured.
+        let mut attrs = String::new();
+        for (name, value) in [
+            ("loading", &self.markdown_config.image_loading),
+            ("decoding", &self.markdown_config.image_decoding),
+            ("fetchpriority", &self.markdown_config.image_fetchpriority),
+        ] {
+            if let Some(value) = value {
+                attrs.push_str(&format!(" {name}=\""));
+                escape_html(&mut attrs, value).unwrap();
+                attrs.push('"');
+            }
+        }
+
+        if !self.markdown_config.responsive_image_widths.is_empty() {
+            if let Some(html) = image::render_responsive_image(
+                dest_url,
+                &escaped_alt,
+                &escaped_title,
+                &attrs,
+                &self.markdown_config.responsive_image_widths,
+                |url| self.transform_image_url(url),
+            ) {
+                return html;
+            }
+        }
+
+        let mut src = String::new();
+        escape_href(&mut src, &self.transform_image_url(dest_url)).unwrap();
+        format!("<img src=\"{src}\" alt=\"{escaped_alt}\" title=\"{escaped_title}\"{attrs}>")
+    }
+
     fn visit_end_tag(&mut self, tag: &TagEnd) -> Visiting {
         match tag {
             TagEnd::Image => {
-                self.processing_image = false;
-                Visiting::Ignore
+                let Some((dest_url, title, alt)) = self.image.take() else {
+                    return Visiting::Ignore;
+                };
+                let event = Event::Html(self.render_image(&dest_url, &title, &alt).into());
+                if let Some(heading) = self.curr_heading.as_mut() {
+                    heading.push_event(event);
+                    Visiting::Ignore
+                } else {
+                    Visiting::Event(event)
+                }
             }
+            _ if self.image.is_some() => Visiting::Ignore,
             TagEnd::CodeBlock => {
+                self.in_code_block = false;
                 self.code_block_fenced = None;
                 Visiting::Ignore
             }
@@ -312,16 +558,29 @@ impl<'a> MarkdownRender<'a> {
                 if let Some(mut heading) = self.curr_heading.take() {
                     self.levels.insert(heading.toc.level);
                     // Render heading event.
-                    let event = heading.render(&self.markdown_env);
+                    let auto_id = self
+                        .markdown_config
+                        .anchor_levels
+                        .contains(&heading.toc.level);
+                    let event = heading.render(&self.markdown_env, auto_id);
                     if let Some(headings) = self.headings.as_mut() {
                         headings.push(heading);
                     }
-                    Visiting::Event(event)
+                    match event {
+                        Ok(event) => Visiting::Event(event),
+                        Err(err) => {
+                            self.error.get_or_insert(err);
+                            Visiting::Ignore
+                        }
+                    }
                 } else {
                     Visiting::Ignore
                 }
             }
             _ => {
+                if let TagEnd::Link = tag {
+                    self.link_depth -= 1;
+                }
                 if let Some(heading) = self.curr_heading.as_mut() {
                     heading.push_event(Event::End(tag.to_owned()));
                     Visiting::Ignore
@@ -333,6 +592,11 @@ impl<'a> MarkdownRender<'a> {
     }
 
     fn visit_text(&mut self, text: &CowStr<'a>) -> Visiting {
+        if let Some((_, _, alt)) = self.image.as_mut() {
+            alt.push_str(text);
+            return Visiting::Ignore;
+        }
+
         if let Some(heading) = self.curr_heading.as_mut() {
             heading
                 .push_text(text.as_ref())
@@ -340,11 +604,6 @@ impl<'a> MarkdownRender<'a> {
             return Visiting::Ignore;
         }
 
-        if self.processing_image {
-            self.image_alt = Some(text.clone());
-            return Visiting::Ignore;
-        }
-
         if let Some(input) = self.code_block_fenced.as_ref() {
             let fenced = Fenced::parse(input).unwrap();
             if fenced.name == code_blocks::URL_PREVIEW
@@ -353,9 +612,13 @@ impl<'a> MarkdownRender<'a> {
                 // Ignore url preview in RSS mode.
                 return Visiting::Ignore;
             } else if fenced.is_builtin_code_block() {
-                let rendered_html = self.render_code_block(fenced, text);
-                if let Some(html) = rendered_html {
-                    return Visiting::Event(Event::Html(html.into()));
+                match self.render_code_block(fenced, text) {
+                    Ok(Some(html)) => return Visiting::Event(Event::Html(html.into())),
+                    Ok(None) => {}
+                    Err(err) => {
+                        self.error.get_or_insert(err);
+                        return Visiting::Ignore;
+                    }
                 }
             } else if let Some(html) = self
                 .visitor
@@ -363,19 +626,67 @@ impl<'a> MarkdownRender<'a> {
                 .and_then(|v| v.visit_custom_block(&fenced, text))
             {
                 return Visiting::Event(Event::Html(html.into()));
+            } else if self.markdown_config.diff_line_classes
+                && matches!(fenced.name, "diff" | "patch")
+            {
+                return Visiting::Event(Event::Html(render_diff(text).into()));
             } else if self.markdown_config.highlight_code {
                 // Syntax highlight
-                let html = self.highlight_syntax(fenced.name, text);
+                let theme = fenced.options.get("theme").map(AsRef::as_ref);
+                let html = self.highlight_syntax(fenced.name, text, theme);
                 return Visiting::Event(Event::Html(html.into()));
             } else {
-                return Visiting::Event(Event::Html(format!("<pre>{}</pre>", text).into()));
+                let mut html = String::from("<pre>");
+                escape_html(&mut html, text).unwrap();
+                html.push_str("</pre>");
+                return Visiting::Event(Event::Html(html.into()));
             }
         }
 
+        if self.in_code_block || self.link_depth > 0 {
+            return Visiting::NotChanged;
+        }
+
+        let abbreviations = &self.markdown_config.abbreviations;
+        let terms = &self.abbreviation_terms;
+        let first_only = self.markdown_config.abbreviations_first_only;
+        let abbreviated = &mut self.abbreviated;
+        let mut abbreviate_text = |text: &str| {
+            if terms.is_empty() {
+                None
+            } else {
+                abbreviate(
+                    text,
+                    abbreviations,
+                    terms,
+                    first_only.then_some(&mut *abbreviated),
+                )
+            }
+        };
+
+        if self.markdown_config.autolink {
+            // Abbreviate the text between the urls.
+            if let Some(html) = autolink(text, |html, text| match abbreviate_text(text) {
+                Some(abbreviated) => html.push_str(&abbreviated),
+                None => escape_html(html, text).unwrap(),
+            }) {
+                return Visiting::Event(Event::Html(html.into()));
+            }
+        }
+
+        if let Some(html) = abbreviate_text(text) {
+            return Visiting::Event(Event::Html(html.into()));
+        }
+
         Visiting::NotChanged
     }
 
     fn visit_code(&mut self, code: &CowStr<'a>) -> Visiting {
+        if let Some((_, _, alt)) = self.image.as_mut() {
+            alt.push_str(code);
+            return Visiting::Ignore;
+        }
+
         if let Some(heading) = self.curr_heading.as_mut() {
             heading
                 .push_text(code.as_ref())
@@ -393,6 +704,305 @@ impl<'a> MarkdownRender<'a> {
     }
 }
 
+// The max times a template can be nested in its own rendering, such as
+// the quote template rendering the markdown of a nested quote.
+const MAX_NESTED_RENDERS: usize = 8;
+
+thread_local! {
+    // The templates rendering by `render_template` in this thread,
+    // the outermost first.
+    static RENDERING_TEMPLATES: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+// Render the template, wrap the error with the template name and
+// the source file currently rendering.
+//
+// Fail with the cyclic template include error if the template renders itself
+// recursively more than `MAX_NESTED_RENDERS` times, the cyclic `{% include %}`
+// inside a template fails with the recursion limit error of minijinja.
+fn render_template<S: Serialize>(env: &Environment, name: &str, ctx: S) -> Result<String> {
+    // Pop the template from the rendering stack on drop.
+    struct RenderingGuard;
+
+    impl Drop for RenderingGuard {
+        fn drop(&mut self) {
+            RENDERING_TEMPLATES.with(|templates| templates.borrow_mut().pop());
+        }
+    }
+
+    let message = || {
+        let source = markdown::current_source_file()
+            .map(|path| format!(" while rendering `{}`", path.display()))
+            .unwrap_or_default();
+        format!("Failed to render template `{name}`{source}")
+    };
+    let cycle = RENDERING_TEMPLATES.with(|templates| {
+        let mut templates = templates.borrow_mut();
+        if templates.iter().filter(|t| *t == name).count() >= MAX_NESTED_RENDERS {
+            let start = templates
+                .iter()
+                .rposition(|t| t == name)
+                .unwrap_or_default();
+            let mut cycle = templates[start..].to_vec();
+            cycle.push(name.to_owned());
+            Some(cycle)
+        } else {
+            templates.push(name.to_owned());
+            None
+        }
+    });
+    if let Some(cycle) = cycle {
+        let err = anyhow::anyhow!("Cyclic template include `{}`", cycle.join(" -> "));
+        return Err(err.context(message()));
+    }
+    let _guard = RenderingGuard;
+
+    env.get_template(name)
+        .and_then(|template| template.render(ctx))
+        .map_err(|err| anyhow::Error::new(err).context(message()))
+}
+
+/// Render the unified diff with a class for each line, so that the added
+/// and removed lines can have the green and red backgrounds.
+fn render_diff(text: &str) -> String {
+    let mut html = String::from("<pre class=\"diff\"><code>");
+    for line in text.lines() {
+        let class = if line.starts_with("+++") || line.starts_with("---") {
+            "diff-header"
+        } else if line.starts_with('+') {
+            "diff-add"
+        } else if line.starts_with('-') {
+            "diff-remove"
+        } else if line.starts_with("@@") {
+            "diff-hunk"
+        } else {
+            "diff-context"
+        };
+        html.push_str(&format!("<span class=\"{class}\">"));
+        escape_html(&mut html, line).unwrap();
+        html.push_str("</span>\n");
+    }
+    html.push_str("</code></pre>");
+    html
+}
+
+// Merge the adjacent text events, which are split by such as the smart punctuation.
+//
+// If `restore_urls`, the smart punctuations inside the bare urls are restored to
+// the source, such as the `–` of `https://a.com/a--b`, so the whole url is autolinked.
+fn merge_text_events<'a>(
+    markdown: &'a str,
+    events: impl Iterator<Item = (Event<'a>, Range<usize>)>,
+    restore_urls: bool,
+) -> Vec<(Event<'a>, Range<usize>)> {
+    let mut merged = Vec::new();
+    let mut texts = Vec::new();
+    for (event, range) in events {
+        match event {
+            Event::Text(text) => texts.push((text, range)),
+            event => {
+                if !texts.is_empty() {
+                    merged.push(merge_texts(markdown, mem::take(&mut texts), restore_urls));
+                }
+                merged.push((event, range));
+            }
+        }
+    }
+    if !texts.is_empty() {
+        merged.push(merge_texts(markdown, texts, restore_urls));
+    }
+    merged
+}
+
+// Merge the non-empty adjacent `texts` into a text event, see `merge_text_events`.
+fn merge_texts<'a>(
+    markdown: &'a str,
+    mut texts: Vec<(CowStr<'a>, Range<usize>)>,
+    restore_urls: bool,
+) -> (Event<'a>, Range<usize>) {
+    if texts.len() == 1 {
+        let (text, range) = texts.remove(0);
+        return (Event::Text(text), range);
+    }
+
+    let range = texts[0].1.start..texts[texts.len() - 1].1.end;
+    // The source of the smart punctuation, such as `--` of `–`.
+    let smart_source = |text: &str, range: &Range<usize>| {
+        markdown.get(range.clone()).filter(|source| {
+            *source != text && matches!(text, "–" | "—" | "‘" | "’" | "“" | "”" | "…")
+        })
+    };
+    let urls = if restore_urls {
+        let source = texts
+            .iter()
+            .map(|(text, range)| smart_source(text, range).unwrap_or(text))
+            .collect::<String>();
+        find_urls(&source)
+    } else {
+        Vec::new()
+    };
+
+    let mut merged = String::new();
+    // The offset in the source with the smart punctuations restored.
+    let mut offset = 0;
+    for (text, range) in &texts {
+        match smart_source(text, range) {
+            Some(source) => {
+                if urls.iter().any(|url| url.contains(&offset)) {
+                    merged.push_str(source);
+                } else {
+                    merged.push_str(text);
+                }
+                offset += source.len();
+            }
+            None => {
+                merged.push_str(text);
+                offset += text.len();
+            }
+        }
+    }
+    (Event::Text(merged.into()), range)
+}
+
+// The ranges of the bare urls in the text.
+fn find_urls(text: &str) -> Vec<Range<usize>> {
+    let mut urls = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = find_url_start(&text[offset..]) {
+        let start = offset + start;
+        let url_len = text[start..]
+            .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"'))
+            .unwrap_or(text.len() - start);
+        let url = trim_url_punctuation(&text[start..start + url_len]);
+        urls.push(start..start + url.len());
+        offset = start + url.len();
+    }
+    urls
+}
+
+/// Convert the bare urls in the text into anchors, the text between
+/// the urls is written by `write_text`, which should escape it.
+///
+/// Return `None` if no url found in the text.
+fn autolink(text: &str, mut write_text: impl FnMut(&mut String, &str)) -> Option<String> {
+    let urls = find_urls(text);
+    if urls.is_empty() {
+        return None;
+    }
+
+    let mut html = String::new();
+    let mut written = 0;
+    for range in urls {
+        write_text(&mut html, &text[written..range.start]);
+        let url = &text[range.clone()];
+        html.push_str("<a href=\"");
+        escape_href(&mut html, url).unwrap();
+        html.push_str("\">");
+        escape_html(&mut html, url).unwrap();
+        html.push_str("</a>");
+        written = range.end;
+    }
+    write_text(&mut html, &text[written..]);
+    Some(html)
+}
+
+// Wrap the whole-word `terms` in `<abbr>` with the definitions of `abbreviations`,
+// the terms in `abbreviated` are skipped and the wrapped terms are recorded if present.
+fn abbreviate<'a>(
+    text: &str,
+    abbreviations: &BTreeMap<String, String>,
+    terms: &[&'a str],
+    mut abbreviated: Option<&mut HashSet<&'a str>>,
+) -> Option<String> {
+    let is_word_char = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+
+    let mut html = String::new();
+    // The start of the text not written into html yet.
+    let mut written = 0;
+    let mut offset = 0;
+    while offset < text.len() {
+        let rest = &text[offset..];
+        let term = (!is_word_char(text[..offset].chars().next_back()))
+            .then(|| {
+                terms.iter().copied().find(|term| {
+                    rest.starts_with(term)
+                        && !is_word_char(rest[term.len()..].chars().next())
+                        && !abbreviated.as_ref().is_some_and(|seen| seen.contains(term))
+                })
+            })
+            .flatten();
+        match term {
+            Some(term) => {
+                escape_html(&mut html, &text[written..offset]).unwrap();
+                html.push_str("<abbr title=\"");
+                escape_html(&mut html, &abbreviations[term]).unwrap();
+                html.push_str("\">");
+                escape_html(&mut html, term).unwrap();
+                html.push_str("</abbr>");
+                if let Some(seen) = abbreviated.as_mut() {
+                    seen.insert(term);
+                }
+                offset += term.len();
+                written = offset;
+            }
+            None => offset += rest.chars().next().map_or(1, char::len_utf8),
+        }
+    }
+
+    if written == 0 {
+        None
+    } else {
+        escape_html(&mut html, &text[written..]).unwrap();
+        Some(html)
+    }
+}
+
+// Find the start of the first bare url, the url must be at the start of a word.
+fn find_url_start(text: &str) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(index) = text[offset..].find("http") {
+        let start = offset + index;
+        let is_word_start = text[..start]
+            .chars()
+            .next_back()
+            .map_or(true, |c| !c.is_alphanumeric());
+        let rest = &text[start..];
+        if is_word_start && (rest.starts_with("http://") || rest.starts_with("https://")) {
+            return Some(start);
+        }
+        offset = start + "http".len();
+    }
+    None
+}
+
+// Trim the trailing punctuations which are unlikely part of the url,
+// a trailing `)` is kept only if the parentheses are balanced.
+fn trim_url_punctuation(url: &str) -> &str {
+    let mut url = url;
+    loop {
+        let trimmed = url.trim_end_matches(['.', ',', ':', ';', '!', '?', '\'', '*']);
+        let trimmed = if trimmed.ends_with(')')
+            && trimmed.matches('(').count() < trimmed.matches(')').count()
+        {
+            &trimmed[..trimmed.len() - 1]
+        } else {
+            trimmed
+        };
+        if trimmed.len() == url.len() {
+            return url;
+        }
+        url = trimmed;
+    }
+}
+
+// Whether the url is an absolute external url or a data URI.
+fn is_external_url(url: &str) -> bool {
+    url.starts_with("http://")
+        || url.starts_with("https://")
+        || url.starts_with("//")
+        || url.starts_with("data:")
+}
+
 /// The markdown visit result.
 enum Visiting {
     /// A new event should be rendered.
@@ -415,3 +1025,416 @@ impl Visiting {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug)]
+    struct ResizeVisitor;
+
+    impl MarkdownVisitor for ResizeVisitor {
+        fn visit_image_url(&self, url: &str) -> Option<String> {
+            Some(format!("{url}?w=800"))
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct UppercaseVisitor;
+
+    impl MarkdownVisitor for UppercaseVisitor {
+        fn transform_events<'a>(&self, events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+            events
+                .into_iter()
+                .map(|event| match event {
+                    Event::Text(text) => Event::Text(text.to_uppercase().into()),
+                    _ => event,
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_highlight_themes() {
+        assert!(check_highlight_theme("monokai").is_ok());
+        assert!(check_highlight_theme("OneHalfDark").is_ok());
+        let themes = check_highlight_theme("not-exists").unwrap_err();
+        assert_eq!(themes.len(), THEME_SET.themes.len());
+        assert!(themes.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(themes.contains(&"monokai"));
+    }
+
+    #[test]
+    fn test_toc_tree() {
+        let config = MarkdownConfig::default();
+        let mut render = MarkdownRender::new(&config);
+        render.enable_toc();
+        render.render_html("### Intro\n## One\n#### One.1\n#### One.2\n## Two\n### Two.1");
+
+        fn titles(nodes: &[TocNode]) -> Vec<String> {
+            nodes
+                .iter()
+                .map(|node| {
+                    let children = titles(&node.children);
+                    if children.is_empty() {
+                        node.toc.title.clone()
+                    } else {
+                        format!("{}[{}]", node.toc.title, children.join(","))
+                    }
+                })
+                .collect()
+        }
+        assert_eq!(
+            titles(&render.get_toc_tree()),
+            ["Intro", "One[One.1,One.2]", "Two[Two.1]"]
+        );
+    }
+
+    #[derive(Clone, Debug)]
+    struct IniVisitor;
+
+    impl MarkdownVisitor for IniVisitor {
+        fn visit_highlight(&self, lang: &str, code: &str) -> Option<String> {
+            (lang == "myconf").then(|| format!("<pre class=\"myconf\">{}</pre>", code.trim()))
+        }
+    }
+
+    #[test]
+    fn test_visit_highlight() {
+        let config = MarkdownConfig::default();
+        let mut render = MarkdownRender::new(&config);
+        render.set_markdown_visitor(Box::new(IniVisitor));
+        assert_eq!(
+            render.render_html("```myconf\nkey = value\n```"),
+            "<pre class=\"myconf\">key = value</pre>"
+        );
+        // The known language is still highlighted by syntect.
+        let html = render.render_html("```rust\nfn main() {}\n```");
+        assert!(!html.contains("myconf") && html.contains("<span"));
+    }
+
+    #[test]
+    fn test_code_block_theme() {
+        let config = MarkdownConfig::default();
+        let mut render = MarkdownRender::new(&config);
+        let default_html = render.render_html("```rust\nfn main() {}\n```");
+        let html = render.render_html("```rust, theme = solarized-light\nfn main() {}\n```");
+        assert_ne!(html, default_html);
+        // Fallback to the configured theme for the unknown theme.
+        let html = render.render_html("```rust, theme = not-exists\nfn main() {}\n```");
+        assert_eq!(html, default_html);
+    }
+
+    #[test]
+    fn test_transform_events() {
+        let config = MarkdownConfig::default();
+        let mut render = MarkdownRender::new(&config);
+        render.set_markdown_visitor(Box::new(UppercaseVisitor));
+        assert_eq!(
+            render.render_html("hello *world*"),
+            "<p>HELLO <em>WORLD</em></p>\n"
+        );
+    }
+
+    #[test]
+    fn test_shift_headings() {
+        let config = MarkdownConfig {
+            shift_headings: true,
+            ..Default::default()
+        };
+        let mut render = MarkdownRender::new(&config);
+        render.enable_toc();
+        let html = render.render_html("# One\n## Two\n###### Six");
+        assert!(html.contains("<h2 id=\"one\""));
+        assert!(html.contains("<h3 id=\"two\""));
+        // Clamped at h6.
+        assert!(html.contains("<h6 id=\"six\""));
+        assert!(!html.contains("<h1") && !html.contains("<h7"));
+
+        let levels = render
+            .get_toc()
+            .into_iter()
+            .map(|toc| (toc.level, toc.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(levels, [(2, 1), (3, 2), (6, 3)]);
+    }
+
+    #[test]
+    fn test_anchor_levels() {
+        let config = MarkdownConfig {
+            anchor_levels: vec![2, 3],
+            ..Default::default()
+        };
+        let mut render = MarkdownRender::new(&config);
+        render.enable_toc();
+        let html = render.render_html("# One\n## Two\n#### Four\n#### Custom {#custom}");
+        assert!(html.contains("<h1 class="));
+        assert!(html.contains("<h2 id=\"two\""));
+        assert!(html.contains("<h4 class="));
+        assert!(html.contains("<h4 id=\"custom\""));
+        assert_eq!(html.matches("href=\"#").count(), 2);
+
+        // The toc entries without id aren't anchorable.
+        let ids = render
+            .get_toc()
+            .into_iter()
+            .map(|toc| toc.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["", "two", "", "custom"]);
+    }
+
+    #[test]
+    fn test_diff_line_classes() {
+        let markdown = "```diff\n--- a/lib.rs\n+++ b/lib.rs\n@@ -1,2 +1,2 @@\n fn main() {\n-    old();\n+    new(<T>);\n```";
+        let config = MarkdownConfig {
+            diff_line_classes: true,
+            ..Default::default()
+        };
+        let html = MarkdownRender::new(&config).render_html(markdown);
+        assert_eq!(
+            html,
+            "<pre class=\"diff\"><code>\
+            <span class=\"diff-header\">--- a/lib.rs</span>\n\
+            <span class=\"diff-header\">+++ b/lib.rs</span>\n\
+            <span class=\"diff-hunk\">@@ -1,2 +1,2 @@</span>\n\
+            <span class=\"diff-context\"> fn main() {</span>\n\
+            <span class=\"diff-remove\">-    old();</span>\n\
+            <span class=\"diff-add\">+    new(&lt;T&gt;);</span>\n\
+            </code></pre>"
+        );
+
+        // Disabled by default.
+        let config = MarkdownConfig::default();
+        let html = MarkdownRender::new(&config).render_html(markdown);
+        assert!(!html.contains("diff-add"));
+    }
+
+    #[test]
+    fn test_autolink() {
+        let config = MarkdownConfig {
+            autolink: true,
+            ..Default::default()
+        };
+        let cases = [
+            (
+                "Visit https://example.com.",
+                r#"<p>Visit <a href="https://example.com">https://example.com</a>.</p>"#,
+            ),
+            (
+                "(see https://example.com/a?b=1), or http://a.com/x!",
+                r#"<p>(see <a href="https://example.com/a?b=1">https://example.com/a?b=1</a>), or <a href="http://a.com/x">http://a.com/x</a>!</p>"#,
+            ),
+            (
+                "wiki: https://example.com/wiki/Rust(lang);",
+                r#"<p>wiki: <a href="https://example.com/wiki/Rust(lang)">https://example.com/wiki/Rust(lang)</a>;</p>"#,
+            ),
+            (
+                "[link](https://a.com) <https://b.com> `https://c.com`",
+                r#"<p><a href="https://a.com">link</a> <a href="https://b.com">https://b.com</a> <code>https://c.com</code></p>"#,
+            ),
+            ("nohttps://a.com", "<p>nohttps://a.com</p>"),
+            // The smart punctuation splits the text events.
+            (
+                "Go https://example.com/a--b?x='1'&y=2 -- it's done",
+                r#"<p>Go <a href="https://example.com/a--b?x=&#x27;1&#x27;&amp;y=2">https://example.com/a--b?x=&#39;1&#39;&amp;y=2</a> – it’s done</p>"#,
+            ),
+        ];
+        for (markdown, expected) in cases {
+            let mut render = MarkdownRender::new(&config);
+            assert_eq!(render.render_html(markdown).trim_end(), expected);
+        }
+    }
+
+    #[test]
+    fn test_image_base_url() {
+        let config = MarkdownConfig {
+            image_base_url: Some("https://cdn.example.com/".into()),
+            ..Default::default()
+        };
+        let mut render = MarkdownRender::new(&config);
+        let html = render.render_html("![](/static/a.png)");
+        assert!(html.contains(r#"src="https://cdn.example.com/static/a.png""#));
+
+        let mut render = MarkdownRender::new(&config);
+        let html = render.render_html("![](https://example.com/a.png)");
+        assert!(html.contains(r#"src="https://example.com/a.png""#));
+    }
+
+    #[test]
+    fn test_image_url_visitor() {
+        let config = MarkdownConfig::default();
+        let mut render = MarkdownRender::new(&config);
+        render.set_markdown_visitor(Box::new(ResizeVisitor));
+        let html = render.render_html("![](a.png)\n\n![](data:image/png;base64,AAAA)");
+        assert!(html.contains(r#"src="a.png?w=800""#));
+        assert!(html.contains(r#"src="data:image/png;base64,AAAA""#));
+
+        let config = MarkdownConfig {
+            transform_external_images: true,
+            ..Default::default()
+        };
+        let mut render = MarkdownRender::new(&config);
+        render.set_markdown_visitor(Box::new(ResizeVisitor));
+        let html = render.render_html("![](https://example.com/a.png)");
+        assert!(html.contains(r#"src="https://example.com/a.png?w=800""#));
+    }
+
+    // Write the png `file` into the shared source directory of the responsive
+    // image tests, which is set as the build directory until the guard drops.
+    fn write_responsive_image(file: &str) -> parking_lot::MutexGuard<'static, ()> {
+        let source = std::env::temp_dir().join("genkit-responsive-image");
+        std::fs::create_dir_all(&source).unwrap();
+        ::image::RgbImage::new(1200, 600)
+            .save(source.join(file))
+            .unwrap();
+        image::lock_build_dirs(&source, &source.join("dist"))
+    }
+
+    #[test]
+    fn test_responsive_image_url() {
+        let _lock = write_responsive_image("cdn.png");
+        let config = MarkdownConfig {
+            image_base_url: Some("https://cdn.example.com/".into()),
+            responsive_image_widths: vec![600],
+            ..Default::default()
+        };
+        let mut render = MarkdownRender::new(&config);
+        let html = render.render_html("![](/cdn.png)");
+        assert!(html.contains(r#"<source type="image/webp" srcset="https://cdn.example.com/cdn-"#));
+        assert!(html.contains(
+            r#"<img src="https://cdn.example.com/cdn.png" srcset="https://cdn.example.com/cdn-"#
+        ));
+        assert!(html.contains(r#", https://cdn.example.com/cdn.png 1200w""#));
+    }
+
+    #[test]
+    fn test_render_template_error() {
+        let mut env = Environment::new();
+        env.add_template("a.jinja", "{% include 'b.jinja' %}")
+            .unwrap();
+        env.add_template("b.jinja", "{% include 'a.jinja' %}")
+            .unwrap();
+        let err = markdown::with_source_file(std::path::Path::new("posts/a.md"), || {
+            render_template(&env, "a.jinja", context! {}).unwrap_err()
+        });
+        assert_eq!(
+            err.to_string(),
+            "Failed to render template `a.jinja` while rendering `posts/a.md`"
+        );
+        assert!(err
+            .root_cause()
+            .to_string()
+            .contains("recursion limit exceeded"));
+
+        let err = render_template(&env, "missing.jinja", context! {}).unwrap_err();
+        assert_eq!(err.to_string(), "Failed to render template `missing.jinja`");
+    }
+
+    #[test]
+    fn test_escape_non_highlighted_code_block() {
+        let config = MarkdownConfig {
+            highlight_code: false,
+            ..Default::default()
+        };
+        let mut render = MarkdownRender::new(&config);
+        let html = render.render_html("```html\n<script>alert('a & b')</script>\n```");
+        assert_eq!(
+            html.trim_end(),
+            "<pre>&lt;script&gt;alert('a &amp; b')&lt;/script&gt;\n</pre>"
+        );
+    }
+
+    #[test]
+    fn test_escape_image_attributes() {
+        let config = MarkdownConfig::default();
+        let mut render = MarkdownRender::new(&config);
+        let html = render.render_html(r#"![a "quoted" > *alt*](/a.png "t\"itle")"#);
+        assert_eq!(
+            html.trim_end(),
+            r#"<p><img src="/a.png" alt="a &quot;quoted&quot; &gt; alt" title="t&quot;itle" loading="lazy" decoding="async"></p>"#
+        );
+
+        // Each image has its own alt.
+        let mut render = MarkdownRender::new(&config);
+        let html = render.render_html("![first](/a.png) ![second](/b.png)");
+        assert!(html.contains(r#"src="/a.png" alt="first""#));
+        assert!(html.contains(r#"src="/b.png" alt="second""#));
+    }
+
+    #[test]
+    fn test_escape_responsive_image_attributes() {
+        let _lock = write_responsive_image("a&b.png");
+        let config = MarkdownConfig {
+            responsive_image_widths: vec![600],
+            ..Default::default()
+        };
+        let mut render = MarkdownRender::new(&config);
+        let html = render.render_html(r#"![a "quoted" > *alt*](/a&b.png "t\"itle")"#);
+        assert!(html.contains(r#"<source type="image/webp" srcset="/a&amp;b-"#));
+        assert!(html.contains(r#"<img src="/a&amp;b.png" srcset="/a&amp;b-"#));
+        assert!(html.contains(
+            r#", /a&amp;b.png 1200w" alt="a &quot;quoted&quot; &gt; alt" title="t&quot;itle" loading="lazy" decoding="async">"#
+        ));
+    }
+
+    #[test]
+    fn test_image_performance_attributes() {
+        let config = MarkdownConfig {
+            image_loading: None,
+            image_fetchpriority: Some("high".into()),
+            ..Default::default()
+        };
+        let mut render = MarkdownRender::new(&config);
+        let html = render.render_html("![](/a.png)");
+        assert!(html.contains(r#"title="" decoding="async" fetchpriority="high">"#));
+        assert!(!html.contains("loading="));
+    }
+
+    #[test]
+    fn test_abbreviations() {
+        let mut config = MarkdownConfig::default();
+        config
+            .abbreviations
+            .insert("HTML".into(), "HyperText <Markup> Language".into());
+        config
+            .abbreviations
+            .insert("API".into(), "Application Programming Interface".into());
+        let markdown = "# HTML\n\nHTML & HTMLX, the API.\n\n`HTML` [HTML](/html) HTML";
+        let mut render = MarkdownRender::new(&config);
+        let html = render.render_html(markdown);
+        let abbr = r#"<abbr title="HyperText &lt;Markup&gt; Language">HTML</abbr>"#;
+        assert!(html.contains(&format!(
+            r#"<p>{abbr} &amp; HTMLX, the <abbr title="Application Programming Interface">API</abbr>.</p>"#
+        )));
+        assert!(html.contains(&format!(
+            r#"<code>HTML</code> <a href="/html">HTML</a> {abbr}"#
+        )));
+        // The heading is skipped.
+        assert_eq!(html.matches("<abbr").count(), 3);
+
+        config.abbreviations_first_only = true;
+        let mut render = MarkdownRender::new(&config);
+        let html = render.render_html(markdown);
+        assert_eq!(html.matches("<abbr").count(), 2);
+    }
+
+    #[test]
+    fn test_abbreviations_with_autolink() {
+        let mut config = MarkdownConfig {
+            autolink: true,
+            ..Default::default()
+        };
+        config
+            .abbreviations
+            .insert("CSS".into(), "Cascading Style Sheets".into());
+        let mut render = MarkdownRender::new(&config);
+        let html = render.render_html("CSS docs at https://x.dev/CSS, or CSS & more");
+        let abbr = r#"<abbr title="Cascading Style Sheets">CSS</abbr>"#;
+        assert_eq!(
+            html.trim_end(),
+            format!(
+                r#"<p>{abbr} docs at <a href="https://x.dev/CSS">https://x.dev/CSS</a>, or {abbr} &amp; more</p>"#
+            )
+        );
+    }
+}
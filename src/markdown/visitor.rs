@@ -1,6 +1,7 @@
 use std::fmt;
 
 use dyn_clone::DynClone;
+use pulldown_cmark::Event;
 
 use crate::code_blocks::Fenced;
 
@@ -10,7 +11,36 @@ pub trait MarkdownVisitor: DynClone + fmt::Debug {
         None
     }
 
+    /// Render the fenced code block which isn't a built-in code block,
+    /// this is consulted before any syntax highlighting.
     fn visit_custom_block(&self, fenced: &Fenced, content: &str) -> Option<String> {
         None
     }
+
+    /// Highlight the code of the language which syntect doesn't know.
+    ///
+    /// The precedence of the fenced code block rendering is:
+    /// built-in code blocks, [`MarkdownVisitor::visit_custom_block`],
+    /// syntect for the known languages, this method, then syntect plain text.
+    /// Only called if `MarkdownConfig::highlight_code` is enabled.
+    fn visit_highlight(&self, lang: &str, code: &str) -> Option<String> {
+        None
+    }
+
+    /// Rewrite the markdown image url, the url has been prefixed with
+    /// `MarkdownConfig::image_base_url` if configured.
+    ///
+    /// External urls and data URIs never reach here unless
+    /// `MarkdownConfig::transform_external_images` is enabled.
+    fn visit_image_url(&self, url: &str) -> Option<String> {
+        None
+    }
+
+    /// Transform the raw markdown event stream before pushing to HTML.
+    ///
+    /// The built-in visiting (heading, code block, image, etc) has been
+    /// applied to the events before this hook. Default to identity.
+    fn transform_events<'a>(&self, events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+        events
+    }
 }
@@ -0,0 +1,217 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+use minijinja::Environment;
+use parking_lot::Mutex;
+use serde::Serialize;
+use time::{format_description, Date};
+
+use crate::{helpers, html, Context};
+
+// All pages generated in current build.
+static GENERATED_PAGES: Mutex<Vec<GeneratedPage>> = parking_lot::const_mutex(Vec::new());
+
+/// The page generated during the build.
+#[derive(Debug, Clone, Serialize)]
+pub struct GeneratedPage {
+    /// The output path of the page, relative to the dest directory.
+    pub path: PathBuf,
+    pub title: Option<String>,
+    #[serde(with = "helpers::serde_date::options")]
+    pub date: Option<Date>,
+}
+
+/// Render the `template` with `context` into the `dest_file`,
+/// and record the page as a [`GeneratedPage`].
+///
+/// The `title` and `date` (`YYYY-MM-DD` format) of the page are captured
+/// from the context if present.
+pub fn render_to_file(
+    env: &Environment,
+    template: &str,
+    context: Context,
+    dest_file: &Path,
+) -> Result<()> {
+    let title = context
+        .get("title")
+        .and_then(|title| title.as_str())
+        .map(ToOwned::to_owned);
+    let date = context
+        .get("date")
+        .and_then(|date| date.as_str())
+        .and_then(|date| {
+            let format = format_description::parse("[year]-[month]-[day]").ok()?;
+            Date::parse(date, &format).ok()
+        });
+    let html = env
+        .get_template(template)?
+        .render(context.into_json())
+        .with_context(|| format!("Failed to render template `{template}`"))?;
+    write_page(dest_file, &html, title, date)
+}
+
+/// Write the `html` into the `dest_file`, and record the page
+/// as a [`GeneratedPage`].
+pub fn write_page(
+    dest_file: &Path,
+    html: &str,
+    title: Option<String>,
+    date: Option<Date>,
+) -> Result<()> {
+    if let Some(parent) = dest_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(dest_file, html)?;
+    GENERATED_PAGES.lock().push(GeneratedPage {
+        path: dest_file.to_path_buf(),
+        title,
+        date,
+    });
+    Ok(())
+}
+
+/// The options of the search index, see [`write_search_index`].
+#[derive(Debug, Clone)]
+pub struct SearchIndexOptions {
+    /// The index file relative to the dest directory, default `search-index.json`.
+    pub filename: String,
+    /// Whether include the full text of the pages, otherwise the summary only.
+    pub full_text: bool,
+    /// The max chars of the summary if `full_text` is disabled.
+    pub summary_length: usize,
+}
+
+impl Default for SearchIndexOptions {
+    fn default() -> Self {
+        SearchIndexOptions {
+            filename: "search-index.json".into(),
+            full_text: true,
+            summary_length: 200,
+        }
+    }
+}
+
+/// The entry of a page in the search index.
+#[derive(Debug, Serialize)]
+pub struct SearchIndexEntry {
+    pub title: Option<String>,
+    pub url: String,
+    pub text: String,
+}
+
+/// Write the JSON search index of the HTML `pages` into the dest directory,
+/// for the client-side search such as lunr or fuse. Call it in
+/// [`crate::Generator::on_finish`]:
+///
+/// ```json
+/// [{ "title": "Hello", "url": "/posts/hello/", "text": "..." }]
+/// ```
+pub fn write_search_index(
+    pages: &[GeneratedPage],
+    dest: &Path,
+    options: &SearchIndexOptions,
+) -> Result<()> {
+    let entries = pages
+        .iter()
+        .filter(|page| page.path.extension().is_some_and(|ext| ext == "html"))
+        .map(|page| {
+            let html = fs::read(dest.join(&page.path))
+                .with_context(|| format!("Failed to read page `{}`", page.path.display()))?;
+            let mut text = html::extract_html_text(html.as_slice());
+            if !options.full_text {
+                if let Some((index, _)) = text.char_indices().nth(options.summary_length) {
+                    text.truncate(index);
+                }
+            }
+            Ok(SearchIndexEntry {
+                title: page.title.clone(),
+                url: page_url(&page.path),
+                text,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    fs::write(
+        dest.join(&options.filename),
+        serde_json::to_string(&entries)?,
+    )?;
+    Ok(())
+}
+
+// The url of the page path relative to the dest directory,
+// such as `posts/hello/index.html` to `/posts/hello/`.
+fn page_url(path: &Path) -> String {
+    let path = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    match path.strip_suffix("index.html") {
+        Some(dir) => format!("/{dir}"),
+        None => format!("/{path}"),
+    }
+}
+
+pub(crate) fn clear_generated_pages() {
+    GENERATED_PAGES.lock().clear();
+}
+
+/// Take all generated pages of current build, the page path is
+/// stripped to be relative to `dest`.
+pub(crate) fn take_generated_pages(dest: &Path) -> Vec<GeneratedPage> {
+    let mut pages = std::mem::take(&mut *GENERATED_PAGES.lock());
+    pages.iter_mut().for_each(|page| {
+        if let Ok(path) = page.path.strip_prefix(dest) {
+            page.path = path.to_path_buf();
+        }
+    });
+    pages.sort_by(|a, b| a.path.cmp(&b.path));
+    pages
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path};
+
+    use super::{page_url, write_search_index, GeneratedPage, SearchIndexOptions};
+
+    #[test]
+    fn test_page_url() {
+        assert_eq!(page_url(Path::new("index.html")), "/");
+        assert_eq!(
+            page_url(Path::new("posts/hello/index.html")),
+            "/posts/hello/"
+        );
+        assert_eq!(page_url(Path::new("about.html")), "/about.html");
+    }
+
+    #[test]
+    fn test_write_search_index() {
+        let dest = env::temp_dir().join("genkit-search-index");
+        fs::create_dir_all(dest.join("posts")).unwrap();
+        fs::write(dest.join("posts/hello.html"), "<p>Hello <b>world</b></p>").unwrap();
+        fs::write(dest.join("feed.xml"), "<rss></rss>").unwrap();
+        let page = |path: &str, title: Option<&str>| GeneratedPage {
+            path: path.into(),
+            title: title.map(ToOwned::to_owned),
+            date: None,
+        };
+        let pages = [
+            page("feed.xml", None),
+            page("posts/hello.html", Some("Hello")),
+        ];
+
+        let options = SearchIndexOptions {
+            full_text: false,
+            summary_length: 5,
+            ..Default::default()
+        };
+        write_search_index(&pages, &dest, &options).unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.join("search-index.json")).unwrap(),
+            r#"[{"title":"Hello","url":"/posts/hello.html","text":"Hello"}]"#
+        );
+    }
+}
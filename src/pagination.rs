@@ -0,0 +1,102 @@
+use serde::Serialize;
+
+/// A page of the paginated items, serializable to render in the template:
+///
+/// ```jinja
+/// {% for post in page.items %}...{% endfor %}
+/// {% if page.prev %}<a href="/page/{{ page.prev }}/">Newer</a>{% endif %}
+/// {% if page.next %}<a href="/page/{{ page.next }}/">Older</a>{% endif %}
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Page<'a, T> {
+    pub items: &'a [T],
+    /// The 1-based page number.
+    pub number: usize,
+    pub total_pages: usize,
+    /// The number of the previous page, `None` for the first page.
+    pub prev: Option<usize>,
+    /// The number of the next page, `None` for the last page.
+    pub next: Option<usize>,
+}
+
+impl<'a, T> Page<'a, T> {
+    pub fn is_first(&self) -> bool {
+        self.number == 1
+    }
+
+    pub fn is_last(&self) -> bool {
+        self.number == self.total_pages
+    }
+}
+
+/// Split the `items` into the pages of `page_size` items, the last page may
+/// have fewer items. The empty `items` has a single empty page, so the list
+/// page is still rendered. The `page_size` of 0 puts all items in one page.
+///
+/// ```rust
+/// # use genkit::pagination::paginate;
+/// let pages = paginate(&[1, 2, 3, 4, 5], 2);
+/// assert_eq!(pages.len(), 3);
+/// assert_eq!(pages[2].items, &[5]);
+/// assert_eq!((pages[1].prev, pages[1].next), (Some(1), Some(3)));
+/// ```
+pub fn paginate<T>(items: &[T], page_size: usize) -> Vec<Page<'_, T>> {
+    let chunks = if items.is_empty() || page_size == 0 {
+        vec![items]
+    } else {
+        items.chunks(page_size).collect()
+    };
+    let total_pages = chunks.len();
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, items)| {
+            let number = index + 1;
+            Page {
+                items,
+                number,
+                total_pages,
+                prev: (number > 1).then(|| number - 1),
+                next: (number < total_pages).then(|| number + 1),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::paginate;
+
+    #[test]
+    fn test_paginate() {
+        let pages = paginate(&[1, 2, 3, 4], 2);
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].items, &[1, 2]);
+        assert_eq!((pages[0].prev, pages[0].next), (None, Some(2)));
+        assert_eq!(pages[1].items, &[3, 4]);
+        assert_eq!((pages[1].prev, pages[1].next), (Some(1), None));
+        assert!(pages[0].is_first() && pages[1].is_last());
+
+        // Single page.
+        let pages = paginate(&[1, 2], 5);
+        assert_eq!(pages.len(), 1);
+        assert_eq!((pages[0].number, pages[0].total_pages), (1, 1));
+        assert_eq!((pages[0].prev, pages[0].next), (None, None));
+
+        // Empty list still has one page.
+        let pages = paginate::<i32>(&[], 5);
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].items.is_empty());
+
+        assert_eq!(paginate(&[1, 2, 3], 0).len(), 1);
+    }
+
+    #[test]
+    fn test_page_json() {
+        let pages = paginate(&["a", "b", "c"], 2);
+        assert_eq!(
+            serde_json::to_string(&pages[1]).unwrap(),
+            r#"{"items":["c"],"number":2,"total_pages":2,"prev":1,"next":null}"#
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+// Whether the build profiling is enabled.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+// The render cost of each entity in current build.
+static ENTITY_COSTS: Mutex<Vec<Duration>> = parking_lot::const_mutex(Vec::new());
+
+pub(crate) fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub(crate) fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Run `f` and record its cost as an entity render cost if profiling enabled.
+pub(crate) fn measure_entity<T, F: FnOnce() -> T>(f: F) -> T {
+    if !is_enabled() {
+        return f();
+    }
+
+    let instant = Instant::now();
+    let result = f();
+    ENTITY_COSTS.lock().push(instant.elapsed());
+    result
+}
+
+/// The profiler to record the cost of each build phase.
+pub(crate) struct Profiler {
+    instant: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        ENTITY_COSTS.lock().clear();
+        Profiler {
+            instant: Instant::now(),
+            phases: Vec::new(),
+        }
+    }
+
+    /// Record the phase cost since the last recorded phase.
+    pub fn phase(&mut self, name: &'static str) {
+        self.phases.push((name, self.instant.elapsed()));
+        self.instant = Instant::now();
+    }
+
+    /// Print the phases cost as a table.
+    pub fn print(&self) {
+        if !is_enabled() {
+            return;
+        }
+
+        println!("{:<12} {:>12}", "Phase", "Cost");
+        for (name, cost) in &self.phases {
+            println!("{:<12} {:>12}", name, format_duration(cost));
+        }
+
+        let costs = ENTITY_COSTS.lock();
+        if let (Some(min), Some(max)) = (costs.iter().min(), costs.iter().max()) {
+            let total = costs.iter().sum::<Duration>();
+            println!(
+                "Entity render: {} entities, min {}, max {}, total {}",
+                costs.len(),
+                format_duration(min),
+                format_duration(max),
+                format_duration(&total),
+            );
+        }
+    }
+}
+
+fn format_duration(duration: &Duration) -> String {
+    format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+}
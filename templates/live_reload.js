@@ -0,0 +1,51 @@
+// The live reload client of the serve command, reconnect with the exponential
+// backoff after the server restarts, then reload once the new build is ready.
+(function () {
+  var protocol = location.protocol === "https:" ? "wss://" : "ws://";
+  var url = protocol + location.host + "__LIVE_RELOAD_PATH__";
+  var minDelay = 500;
+  var maxDelay = 10000;
+  var delay = minDelay;
+  var disconnected = false;
+
+  // Reload after the build finished, otherwise the page may be absent yet.
+  function reloadWhenReady() {
+    fetch("/__genkit/status")
+      .then(function (response) {
+        return response.json();
+      })
+      .then(function (status) {
+        if (status.state === "building") {
+          setTimeout(reloadWhenReady, minDelay);
+        } else {
+          location.reload();
+        }
+      })
+      .catch(function () {
+        setTimeout(reloadWhenReady, minDelay);
+      });
+  }
+
+  function connect() {
+    var socket = new WebSocket(url);
+    socket.onopen = function () {
+      delay = minDelay;
+      // Pick up the changes made while the server was down.
+      if (disconnected) {
+        reloadWhenReady();
+      }
+    };
+    socket.onmessage = function (event) {
+      if (event.data === "reload") {
+        location.reload();
+      }
+    };
+    socket.onclose = function () {
+      disconnected = true;
+      setTimeout(connect, delay);
+      delay = Math.min(delay * 2, maxDelay);
+    };
+  }
+
+  connect();
+})();